@@ -2,3 +2,15 @@ cargo build --no-default-features
 cargo build --no-default-features --features "otel"
 cargo build --no-default-features --features "otel, openapi"
 cargo build --no-default-features --features "openapi"
+cargo build --no-default-features --features "syslog"
+cargo build --no-default-features --features "otel-grpc"
+cargo build --no-default-features --features "otel-http-json"
+cargo build --no-default-features --features "otel-gzip, otel-tls"
+cargo build --no-default-features --features "otel-concurrent-export"
+cargo build --no-default-features --features "otel-b3, otel-jaeger"
+cargo build --no-default-features --features "otel-http"
+cargo build --no-default-features --features "otel-axum"
+cargo build --no-default-features --features "otel-http-client"
+cargo build --no-default-features --features "otel-stdout, otel-in-memory"
+cargo build --no-default-features --features "otel-prometheus"
+cargo build --no-default-features --features "otel-tokio"
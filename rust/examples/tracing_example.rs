@@ -13,11 +13,13 @@ struct CreateOrderRequest {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct CreateOrderResponse {
     order_id: String,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct ApiError {
     message: String,
     code: String,
@@ -61,7 +63,7 @@ impl IntoOtelAttributes for DbQueryMetrics {
             attrs.push(opentelemetry::KeyValue::new("error.type", "db_error"));
         }
 
-        Vec::from(attrs)
+        attrs
     }
 }
 #[cfg(feature = "otel")]
@@ -258,11 +260,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .with_json(Some(false))
         .with_otel_metric_export_interval("5000")
         .with_otel(OtelParams {
-            endpoint_traces: Some("http://localhost:4318/v1/traces".into()),
-            endpoint_metrics: Some("http://localhost:4318/v1/metrics".into()),
-            endpoint_logs: Some("http://localhost:4318/v1/logs".into()),
+            endpoint: Some("http://localhost:4318".into()),
             service_name: "order-service".into(),
             service_version: "1.0.0".into(),
+            ..Default::default()
         })
         .try_init()?;
 
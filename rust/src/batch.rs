@@ -0,0 +1,440 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use opentelemetry::{Context, InstrumentationScope, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::logs::{
+    BatchLogProcessor, LogBatch, LogExporter, LogProcessor, SdkLogRecord,
+};
+use opentelemetry_sdk::trace::{Span, SpanData, SpanExporter, SpanProcessor};
+
+const DEFAULT_MAX_QUEUE_SIZE: usize = 2_048;
+
+/// Batch processor settings for spans and logs, see `OtelParams::batch`.
+///
+/// Unset values fall back to the `OTEL_BSP_*` / `OTEL_BLRP_*` environment
+/// variables, then to the SDK defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchSettings {
+    /// Items buffered before new ones are dropped, SDK default 2048
+    pub max_queue_size: Option<usize>,
+    /// Items per export request, SDK default 512
+    pub max_export_batch_size: Option<usize>,
+    /// Longest wait before a partial batch is exported, SDK default 5s for
+    /// spans and 1s for logs
+    pub scheduled_delay: Option<Duration>,
+    /// Span exports in flight at once, more than one needs the
+    /// `otel-concurrent-export` feature and a Tokio runtime at init.
+    /// Logs are always exported one batch at a time.
+    pub max_concurrent_exports: Option<usize>,
+}
+
+impl BatchSettings {
+    fn queue_size(&self, env_var: &str) -> usize {
+        self.max_queue_size
+            .or_else(|| std::env::var(env_var).ok()?.parse().ok())
+            .unwrap_or(DEFAULT_MAX_QUEUE_SIZE)
+    }
+}
+
+/// Counts items between a batch processor and its exporter.
+///
+/// The SDK keeps its own drop count private, so the queue bound is enforced
+/// here in front of the processor, which then never overflows itself.
+struct QueueLimit {
+    len: AtomicUsize,
+    capacity: usize,
+    counter_name: &'static str,
+    dropped: OnceLock<opentelemetry::metrics::Counter<u64>>,
+}
+
+impl std::fmt::Debug for QueueLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueueLimit")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl QueueLimit {
+    fn new(capacity: usize, counter_name: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            len: AtomicUsize::new(0),
+            capacity,
+            counter_name,
+            dropped: OnceLock::new(),
+        })
+    }
+
+    /// Reserves a slot, records a drop when the queue is full.
+    fn try_push(&self) -> bool {
+        let pushed = self
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                (len < self.capacity).then_some(len + 1)
+            })
+            .is_ok();
+        if !pushed {
+            // Created on first use so it binds to the meter provider
+            // installed after the tracer and logger providers.
+            self.dropped
+                .get_or_init(|| {
+                    opentelemetry::global::meter("internal-utils")
+                        .u64_counter(self.counter_name)
+                        .with_description("Telemetry dropped because the batch queue was full")
+                        .with_unit("1")
+                        .build()
+                })
+                .add(1, &[KeyValue::new("reason", "queue_full")]);
+        }
+        pushed
+    }
+
+    fn pop(&self, count: usize) {
+        _ = self
+            .len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                Some(len.saturating_sub(count))
+            });
+    }
+}
+
+#[derive(Debug)]
+struct QueuedSpanExporter<E> {
+    inner: E,
+    queue: Arc<QueueLimit>,
+}
+
+impl<E: SpanExporter> SpanExporter for QueuedSpanExporter<E> {
+    fn export(
+        &self,
+        batch: Vec<SpanData>,
+    ) -> impl std::future::Future<Output = OTelSdkResult> + Send {
+        self.queue.pop(batch.len());
+        self.inner.export(batch)
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Batch span processor counting spans dropped on queue overflow as
+/// `otel.batch.spans.dropped`.
+#[derive(Debug)]
+pub(crate) struct QueuedSpanProcessor {
+    inner: Box<dyn SpanProcessor>,
+    queue: Arc<QueueLimit>,
+}
+
+impl QueuedSpanProcessor {
+    pub fn new<E>(
+        exporter: E,
+        settings: &BatchSettings,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>>
+    where
+        E: SpanExporter + 'static,
+    {
+        use opentelemetry_sdk::trace::BatchConfigBuilder;
+
+        let capacity = settings.queue_size("OTEL_BSP_MAX_QUEUE_SIZE");
+        let queue = QueueLimit::new(capacity, "otel.batch.spans.dropped");
+        let exporter = QueuedSpanExporter {
+            inner: exporter,
+            queue: queue.clone(),
+        };
+
+        let mut config = BatchConfigBuilder::default().with_max_queue_size(capacity);
+        if let Some(size) = settings.max_export_batch_size {
+            config = config.with_max_export_batch_size(size);
+        }
+        if let Some(delay) = settings.scheduled_delay {
+            config = config.with_scheduled_delay(delay);
+        }
+
+        let inner: Box<dyn SpanProcessor> = match settings.max_concurrent_exports {
+            #[cfg(feature = "otel-concurrent-export")]
+            Some(exports) if exports > 1 => {
+                use opentelemetry_sdk::trace::span_processor_with_async_runtime::BatchSpanProcessor;
+
+                let config = config.with_max_concurrent_exports(exports).build();
+                Box::new(
+                    BatchSpanProcessor::builder(exporter, opentelemetry_sdk::runtime::Tokio)
+                        .with_batch_config(config)
+                        .build(),
+                )
+            }
+            #[cfg(not(feature = "otel-concurrent-export"))]
+            Some(exports) if exports > 1 => {
+                return Err(
+                    "max_concurrent_exports above 1 requires the `otel-concurrent-export` feature"
+                        .into(),
+                );
+            }
+            _ => Box::new(
+                opentelemetry_sdk::trace::BatchSpanProcessor::builder(exporter)
+                    .with_batch_config(config.build())
+                    .build(),
+            ),
+        };
+        Ok(Self { inner, queue })
+    }
+}
+
+impl SpanProcessor for QueuedSpanProcessor {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // Record-only spans are never exported. The sync batch processor would
+        // still queue and export them, so they are dropped here before being
+        // counted.
+        if span.span_context.is_sampled() && self.queue.try_push() {
+            self.inner.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[derive(Debug)]
+struct QueuedLogExporter<E> {
+    inner: E,
+    queue: Arc<QueueLimit>,
+}
+
+impl<E: LogExporter> LogExporter for QueuedLogExporter<E> {
+    fn export(
+        &self,
+        batch: LogBatch<'_>,
+    ) -> impl std::future::Future<Output = OTelSdkResult> + Send {
+        self.queue.pop(batch.iter().count());
+        self.inner.export(batch)
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Batch log processor counting records dropped on queue overflow as
+/// `otel.batch.logs.dropped`.
+#[derive(Debug)]
+pub(crate) struct QueuedLogProcessor {
+    inner: BatchLogProcessor,
+    queue: Arc<QueueLimit>,
+}
+
+impl QueuedLogProcessor {
+    pub fn new<E>(exporter: E, settings: &BatchSettings) -> Self
+    where
+        E: LogExporter + 'static,
+    {
+        use opentelemetry_sdk::logs::BatchConfigBuilder;
+
+        let capacity = settings.queue_size("OTEL_BLRP_MAX_QUEUE_SIZE");
+        let queue = QueueLimit::new(capacity, "otel.batch.logs.dropped");
+        let exporter = QueuedLogExporter {
+            inner: exporter,
+            queue: queue.clone(),
+        };
+
+        let mut config = BatchConfigBuilder::default().with_max_queue_size(capacity);
+        if let Some(size) = settings.max_export_batch_size {
+            config = config.with_max_export_batch_size(size);
+        }
+        if let Some(delay) = settings.scheduled_delay {
+            config = config.with_scheduled_delay(delay);
+        }
+        let inner = BatchLogProcessor::builder(exporter)
+            .with_batch_config(config.build())
+            .build();
+        Self { inner, queue }
+    }
+}
+
+impl LogProcessor for QueuedLogProcessor {
+    fn emit(&self, record: &mut SdkLogRecord, scope: &InstrumentationScope) {
+        if self.queue.try_push() {
+            self.inner.emit(record, scope);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BatchSettings, QueuedSpanProcessor};
+    use opentelemetry::trace::{
+        Link, SamplingDecision, SamplingResult, SpanKind, TraceId, Tracer, TracerProvider,
+    };
+    use opentelemetry::{Context, KeyValue};
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::{
+        InMemorySpanExporter, SdkTracerProvider, ShouldSample, SpanData, SpanExporter,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{Receiver, channel};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Blocks every export until the gate sender is dropped.
+    #[derive(Debug)]
+    struct GatedExporter {
+        gate: Mutex<Receiver<()>>,
+        exported: Arc<AtomicUsize>,
+    }
+
+    impl SpanExporter for GatedExporter {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            _ = self
+                .gate
+                .lock()
+                .unwrap()
+                .recv_timeout(Duration::from_secs(5));
+            self.exported.fetch_add(batch.len(), Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn queue_overflow_is_counted() {
+        let metrics = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        opentelemetry::global::set_meter_provider(meter_provider.clone());
+
+        let (release, gate) = channel();
+        let exported = Arc::new(AtomicUsize::new(0));
+        let exporter = GatedExporter {
+            gate: Mutex::new(gate),
+            exported: exported.clone(),
+        };
+        let settings = BatchSettings {
+            max_queue_size: Some(2),
+            scheduled_delay: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(QueuedSpanProcessor::new(exporter, &settings).unwrap())
+            .build();
+        let tracer = provider.tracer("test");
+
+        for _ in 0..10 {
+            tracer.in_span("burst", |_| {});
+        }
+        drop(release);
+        provider.force_flush().unwrap();
+        meter_provider.force_flush().unwrap();
+
+        let dropped = crate::test::metric_sum(&metrics, "otel.batch.spans.dropped");
+        // One batch blocked in the exporter plus one queued, the rest dropped.
+        assert!(dropped >= 6, "dropped {dropped}");
+        assert_eq!(exported.load(Ordering::SeqCst) as u64 + dropped, 10);
+    }
+
+    /// Samples spans named `sampled` and only records the others.
+    #[derive(Debug, Clone)]
+    struct RecordOnly;
+
+    impl ShouldSample for RecordOnly {
+        fn should_sample(
+            &self,
+            _parent_context: Option<&Context>,
+            _trace_id: TraceId,
+            name: &str,
+            _span_kind: &SpanKind,
+            _attributes: &[KeyValue],
+            _links: &[Link],
+        ) -> SamplingResult {
+            SamplingResult {
+                decision: match name {
+                    "sampled" => SamplingDecision::RecordAndSample,
+                    _ => SamplingDecision::RecordOnly,
+                },
+                attributes: Vec::new(),
+                trace_state: Default::default(),
+            }
+        }
+    }
+
+    #[test]
+    pub fn record_only_spans_are_not_queued() {
+        let exporter = InMemorySpanExporter::default();
+        let settings = BatchSettings {
+            max_queue_size: Some(4),
+            ..Default::default()
+        };
+        let processor = QueuedSpanProcessor::new(exporter.clone(), &settings).unwrap();
+        let queue = processor.queue.clone();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(processor)
+            .with_sampler(RecordOnly)
+            .build();
+        let tracer = provider.tracer("test");
+
+        for _ in 0..10 {
+            tracer.in_span("recorded", |_| {});
+        }
+        tracer.in_span("sampled", |_| {});
+        tracer.in_span("sampled", |_| {});
+        assert_eq!(queue.len.load(Ordering::SeqCst), 2);
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|span| span.name == "sampled"));
+        assert_eq!(queue.len.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "otel-concurrent-export"))]
+    pub fn concurrent_exports_need_feature() {
+        let settings = BatchSettings {
+            max_concurrent_exports: Some(4),
+            ..Default::default()
+        };
+        let exporter = opentelemetry_sdk::trace::InMemorySpanExporter::default();
+        assert!(QueuedSpanProcessor::new(exporter, &settings).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Histogram, Meter};
+use tracing::Instrument;
+use tracing::field::Empty;
+
+use crate::MetricsHelper;
+
+/// Tower layer for outbound HTTP clients, e.g. a hyper-util `Client`.
+///
+/// Each request gets a client span with the semconv HTTP attributes, the
+/// span context is injected into the request headers with the global
+/// propagator and the call is recorded in `http.client.request.duration`.
+#[derive(Clone)]
+pub struct HttpClientLayer {
+    duration: Histogram<u64>,
+}
+
+impl HttpClientLayer {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            duration: MetricsHelper::http_client_request_duration(meter),
+        }
+    }
+}
+
+impl<S> tower_layer::Layer<S> for HttpClientLayer {
+    type Service = HttpClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpClientService {
+            inner,
+            duration: self.duration.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpClientService<S> {
+    inner: S,
+    duration: Histogram<u64>,
+}
+
+impl<S, B, R> tower_service::Service<http::Request<B>> for HttpClientService<S>
+where
+    S: tower_service::Service<http::Request<B>, Response = http::Response<R>>,
+    S::Error: std::fmt::Display,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let start = Instant::now();
+        let method = crate::method::normalize(request.method());
+        let uri = request.uri();
+        let address = uri.host().unwrap_or_default().to_owned();
+        let port = uri.port_u16().unwrap_or_else(|| match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+
+        let span = tracing::info_span!(
+            "http.client",
+            otel.name = crate::method::span_name(method),
+            otel.kind = "client",
+            otel.status_code = Empty,
+            http.request.method = method,
+            http.request.method_original = Empty,
+            server.address = %address,
+            server.port = port,
+            url.full = %crate::url::strip_credentials(&uri.to_string()),
+            http.response.status_code = Empty,
+            error.type = Empty,
+        );
+        if method == crate::method::OTHER {
+            span.record("http.request.method_original", request.method().as_str());
+        }
+        span.in_scope(|| crate::inject_context(request.headers_mut()));
+
+        let future = self.inner.call(request);
+        let duration = self.duration.clone();
+        Box::pin(
+            async move {
+                let result = future.await;
+                let span = tracing::Span::current();
+                let mut attributes = vec![
+                    KeyValue::new("http.request.method", method),
+                    KeyValue::new("server.address", address),
+                    KeyValue::new("server.port", port as i64),
+                ];
+
+                match &result {
+                    Ok(response) => {
+                        let status = response.status();
+                        span.record("http.response.status_code", status.as_u16());
+                        attributes.push(KeyValue::new(
+                            "http.response.status_code",
+                            status.as_u16() as i64,
+                        ));
+                        // Unlike server spans, 4xx is a failed call for the client.
+                        if status.is_client_error() || status.is_server_error() {
+                            span.record("otel.status_code", "ERROR");
+                            span.record("error.type", status.as_str());
+                            attributes
+                                .push(KeyValue::new("error.type", status.as_str().to_owned()));
+                        }
+                    }
+                    Err(error) => {
+                        span.record("otel.status_code", "ERROR");
+                        span.record("error.type", "_OTHER");
+                        tracing::error!(error = %error, "Request failed");
+                        attributes.push(KeyValue::new("error.type", "_OTHER"));
+                    }
+                }
+
+                duration.record(start.elapsed().as_millis() as u64, &attributes);
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HttpClientLayer;
+    use opentelemetry::trace::{SpanKind, Status, TracerProvider};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use tower::{Layer, ServiceExt};
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    pub async fn requests_carry_context_and_are_measured() {
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let spans = InMemorySpanExporter::default();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spans.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let metrics = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let layer = HttpClientLayer::new(&opentelemetry::metrics::MeterProvider::meter(
+            &meter_provider,
+            "test",
+        ));
+
+        // Echoes the injected traceparent back as the body.
+        let client = layer.layer(tower::service_fn(|request: http::Request<()>| async move {
+            let traceparent = request.headers()["traceparent"]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let status = if request.uri().path() == "/missing" {
+                404
+            } else {
+                200
+            };
+            Ok::<_, std::convert::Infallible>(
+                http::Response::builder()
+                    .status(status)
+                    .body(traceparent)
+                    .unwrap(),
+            )
+        }));
+
+        let request = http::Request::get("https://user:pw@rpc.example.com/blocks?key=secret")
+            .body(())
+            .unwrap();
+        let traceparent = async { client.clone().oneshot(request).await.unwrap().into_body() }
+            .instrument(tracing::info_span!("sync_blocks"))
+            .await;
+        let request = http::Request::get("http://rpc.example.com:8080/missing")
+            .body(())
+            .unwrap();
+        client.clone().oneshot(request).await.unwrap();
+        let request = http::Request::builder()
+            .method("PURGE")
+            .uri("http://rpc.example.com/cache")
+            .body(())
+            .unwrap();
+        client.oneshot(request).await.unwrap();
+
+        let finished = spans.get_finished_spans().unwrap();
+        let call = &finished[0];
+        let parent = finished.iter().find(|s| s.name == "sync_blocks").unwrap();
+        assert_eq!(call.span_kind, SpanKind::Client);
+        assert_eq!(call.parent_span_id, parent.span_context.span_id());
+        assert!(traceparent.contains(&call.span_context.span_id().to_string()));
+        let url = call
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == "url.full")
+            .unwrap();
+        assert_eq!(url.value.as_str(), "https://rpc.example.com/blocks");
+        assert!(matches!(finished[2].status, Status::Error { .. }));
+        let purge = &finished[3];
+        assert_eq!(purge.name, "HTTP");
+        let method = |key: &str| {
+            purge
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.to_string())
+        };
+        assert_eq!(method("http.request.method").as_deref(), Some("_OTHER"));
+        assert_eq!(
+            method("http.request.method_original").as_deref(),
+            Some("PURGE")
+        );
+
+        meter_provider.force_flush().unwrap();
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        assert!(
+            snapshot
+                .last()
+                .unwrap()
+                .scope_metrics()
+                .flat_map(|scope| scope.metrics())
+                .any(|metric| metric.name() == "http.client.request.duration")
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Records errors on a span the way OpenTelemetry expects them.
+pub trait SpanErrorExt {
+    /// Adds an `exception` event with `exception.type`, `exception.message`,
+    /// `exception.causes` (the `source()` chain, outermost first) and
+    /// `exception.stacktrace` when `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+    /// enables backtraces, then sets the span status to error.
+    ///
+    /// `exception.type` is the Rust type name of `E` and is left out for
+    /// trait objects such as `dyn Error`, whose concrete type is unknown.
+    /// The backtrace is captured here, so it shows where the error was
+    /// recorded rather than where it was created.
+    fn record_error<E: Error + ?Sized>(&self, error: &E);
+}
+
+impl SpanErrorExt for tracing::Span {
+    fn record_error<E: Error + ?Sized>(&self, error: &E) {
+        let message = error.to_string();
+        let mut attributes = vec![KeyValue::new("exception.message", message.clone())];
+        let type_name = std::any::type_name::<E>();
+        if !type_name.starts_with("dyn ") {
+            attributes.push(KeyValue::new("exception.type", type_name));
+        }
+        let mut causes: Vec<StringValue> = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string().into());
+            source = cause.source();
+        }
+        if !causes.is_empty() {
+            attributes.push(KeyValue::new(
+                "exception.causes",
+                Value::Array(Array::String(causes)),
+            ));
+        }
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            attributes.push(KeyValue::new("exception.stacktrace", backtrace.to_string()));
+        }
+        self.add_event("exception", attributes);
+        self.set_status(Status::error(message));
+    }
+}
+
+/// Records the error of a `Result` on a span and passes the `Result` on.
+pub trait ResultErrorExt: Sized {
+    /// Records an `Err` on the current span, see `SpanErrorExt::record_error`.
+    fn record_err(self) -> Self {
+        self.record_err_in(&tracing::Span::current())
+    }
+
+    /// Records an `Err` on `span`.
+    fn record_err_in(self, span: &tracing::Span) -> Self;
+}
+
+impl<T, E: Error> ResultErrorExt for Result<T, E> {
+    fn record_err_in(self, span: &tracing::Span) -> Self {
+        if let Err(error) = &self {
+            span.record_error(error);
+        }
+        self
+    }
+}
+
+/// `ResultErrorExt` for boxed errors, which do not implement `Error`:
+/// `Box<dyn Error + Send + Sync>` as returned by `TracingBuilder::try_init`,
+/// or `anyhow::Error`.
+pub trait DynResultErrorExt: Sized {
+    /// Records an `Err` on the current span, see `SpanErrorExt::record_error`.
+    fn record_err(self) -> Self {
+        self.record_err_in(&tracing::Span::current())
+    }
+
+    /// Records an `Err` on `span`.
+    fn record_err_in(self, span: &tracing::Span) -> Self;
+}
+
+impl<T, E: AsRef<dyn Error + Send + Sync>> DynResultErrorExt for Result<T, E> {
+    fn record_err_in(self, span: &tracing::Span) -> Self {
+        if let Err(error) = &self {
+            span.record_error(error.as_ref());
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DynResultErrorExt, ResultErrorExt, SpanErrorExt};
+    use opentelemetry::trace::{Status, TracerProvider};
+    use opentelemetry::{Array, Value};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Debug)]
+    struct QueryError(std::io::Error);
+
+    impl std::fmt::Display for QueryError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("query failed")
+        }
+    }
+
+    impl std::error::Error for QueryError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn query() -> Result<u32, QueryError> {
+        Err(QueryError(std::io::Error::other("connection reset")))
+    }
+
+    fn load() -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(query()?)
+    }
+
+    #[test]
+    pub fn errors_are_recorded_as_exception_events() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("load_orders").in_scope(|| {
+                assert!(query().record_err().is_err());
+            });
+            let span = tracing::info_span!("parse_config");
+            span.record_error(&"x".parse::<u8>().unwrap_err());
+            tracing::info_span!("boxed").in_scope(|| {
+                assert!(load().record_err().is_err());
+            });
+            tracing::info_span!("ok").in_scope(|| {
+                assert_eq!(Ok::<_, QueryError>(1).record_err().unwrap(), 1);
+            });
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |name: &str| spans.iter().find(|span| span.name == name).unwrap();
+
+        let load = span("load_orders");
+        assert_eq!(load.status, Status::error("query failed"));
+        let event = &load.events[0];
+        assert_eq!(event.name, "exception");
+        let attribute = |key: &str| {
+            event
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.clone())
+        };
+        assert_eq!(
+            attribute("exception.type"),
+            Some(Value::from(std::any::type_name::<QueryError>()))
+        );
+        assert_eq!(
+            attribute("exception.message"),
+            Some(Value::from("query failed"))
+        );
+        assert_eq!(
+            attribute("exception.causes"),
+            Some(Value::Array(Array::String(vec!["connection reset".into()])))
+        );
+
+        let parse = span("parse_config");
+        assert_eq!(parse.events[0].name, "exception");
+        assert!(matches!(parse.status, Status::Error { .. }));
+
+        let boxed = &span("boxed").events[0];
+        let keys: Vec<_> = boxed.attributes.iter().map(|kv| kv.key.as_str()).collect();
+        assert!(keys.contains(&"exception.message"));
+        assert!(keys.contains(&"exception.causes"));
+        assert!(!keys.contains(&"exception.type"));
+
+        let ok = span("ok");
+        assert!(ok.events.is_empty());
+        assert_eq!(ok.status, Status::Unset);
+    }
+}
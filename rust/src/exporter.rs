@@ -0,0 +1,595 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "otel-stdout")]
+use std::io::Write;
+#[cfg(feature = "otel-stdout")]
+use std::sync::Mutex;
+use std::time::Duration;
+
+use opentelemetry_sdk::Resource;
+#[cfg(feature = "otel-stdout")]
+use opentelemetry_sdk::error::OTelSdkError;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+#[cfg(feature = "otel-in-memory")]
+use opentelemetry_sdk::{
+    logs::InMemoryLogExporter, metrics::InMemoryMetricExporter, trace::InMemorySpanExporter,
+};
+
+use crate::{OtelParams, otlp};
+
+/// Destination of one signal, see `OtelParams::exporter_traces`.
+///
+/// `Stdout` and `StdoutOtlpJson` require the `otel-stdout` feature,
+/// `InMemory` requires `otel-in-memory`. Only `Otlp` needs an endpoint.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SignalExporter {
+    /// OTLP to the configured endpoint
+    #[default]
+    Otlp,
+    /// Human readable text on stdout
+    #[cfg(feature = "otel-stdout")]
+    Stdout,
+    /// One OTLP-JSON `Export*ServiceRequest` per line on stdout, the same
+    /// encoding as the OTLP file exporter
+    #[cfg(feature = "otel-stdout")]
+    StdoutOtlpJson,
+    /// Kept in memory, read it through `OtelGuards::in_memory`
+    #[cfg(feature = "otel-in-memory")]
+    InMemory,
+}
+
+impl SignalExporter {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Otlp => "otlp",
+            #[cfg(feature = "otel-stdout")]
+            Self::Stdout => "stdout",
+            #[cfg(feature = "otel-stdout")]
+            Self::StdoutOtlpJson => "stdout/otlp-json",
+            #[cfg(feature = "otel-in-memory")]
+            Self::InMemory => "in-memory",
+        }
+    }
+}
+
+impl Display for SignalExporter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Exporters of the signals configured with `SignalExporter::InMemory`.
+#[cfg(feature = "otel-in-memory")]
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryExporters {
+    pub spans: Option<InMemorySpanExporter>,
+    pub metrics: Option<InMemoryMetricExporter>,
+    pub logs: Option<InMemoryLogExporter>,
+}
+
+/// Calls `$body` with the exporter wrapped by any variant of `Self`.
+macro_rules! dispatch {
+    ($exporter:expr, $inner:ident => $body:expr) => {
+        match $exporter {
+            Self::Otlp($inner) => $body,
+            #[cfg(feature = "otel-stdout")]
+            Self::Stdout($inner) => $body,
+            #[cfg(feature = "otel-stdout")]
+            Self::OtlpJson($inner) => $body,
+            #[cfg(feature = "otel-in-memory")]
+            Self::InMemory($inner) => $body,
+        }
+    };
+}
+
+// Exporters are built once per provider, boxing the OTLP ones buys nothing.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(crate) enum AnySpanExporter {
+    Otlp(opentelemetry_otlp::SpanExporter),
+    #[cfg(feature = "otel-stdout")]
+    Stdout(opentelemetry_stdout::SpanExporter),
+    #[cfg(feature = "otel-stdout")]
+    OtlpJson(OtlpJsonSpanExporter),
+    #[cfg(feature = "otel-in-memory")]
+    InMemory(InMemorySpanExporter),
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(crate) enum AnyMetricExporter {
+    Otlp(opentelemetry_otlp::MetricExporter),
+    #[cfg(feature = "otel-stdout")]
+    Stdout(opentelemetry_stdout::MetricExporter),
+    #[cfg(feature = "otel-stdout")]
+    OtlpJson(OtlpJsonMetricExporter),
+    #[cfg(feature = "otel-in-memory")]
+    InMemory(InMemoryMetricExporter),
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(crate) enum AnyLogExporter {
+    Otlp(opentelemetry_otlp::LogExporter),
+    #[cfg(feature = "otel-stdout")]
+    Stdout(opentelemetry_stdout::LogExporter),
+    #[cfg(feature = "otel-stdout")]
+    OtlpJson(OtlpJsonLogExporter),
+    #[cfg(feature = "otel-in-memory")]
+    InMemory(InMemoryLogExporter),
+}
+
+/// Names of the exporters built by `span_exporter`, `metric_exporter` and
+/// `log_exporter`, each listed once.
+pub(crate) fn sinks(params: &OtelParams) -> Vec<&'static str> {
+    let signals = [
+        (
+            params.exporter_traces,
+            params.traces_enabled,
+            params.traces_endpoint(),
+        ),
+        (
+            params.exporter_metrics,
+            params.metrics_enabled,
+            params.metrics_endpoint(),
+        ),
+        (
+            params.exporter_logs,
+            params.logs_enabled,
+            params.logs_endpoint(),
+        ),
+    ];
+    let mut sinks = Vec::new();
+    for (exporter, enabled, endpoint) in signals {
+        let built = if exporter == SignalExporter::Otlp {
+            endpoint.is_some()
+        } else {
+            enabled
+        };
+        if built && !sinks.contains(&exporter.name()) {
+            sinks.push(exporter.name());
+        }
+    }
+    sinks
+}
+
+/// Span exporter for `exporter_traces`, None when traces are disabled or
+/// OTLP has no endpoint.
+pub(crate) fn span_exporter(
+    params: &OtelParams,
+) -> Result<Option<AnySpanExporter>, Box<dyn Error + Send + Sync>> {
+    if !params.traces_enabled {
+        return Ok(None);
+    }
+    Ok(Some(match params.exporter_traces {
+        SignalExporter::Otlp => {
+            let Some(endpoint) = params.traces_endpoint() else {
+                return Ok(None);
+            };
+            AnySpanExporter::Otlp(otlp::span_exporter(
+                params.protocol_traces,
+                endpoint,
+                params,
+            )?)
+        }
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::Stdout => AnySpanExporter::Stdout(Default::default()),
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::StdoutOtlpJson => {
+            AnySpanExporter::OtlpJson(OtlpJsonSpanExporter::new(std::io::stdout()))
+        }
+        #[cfg(feature = "otel-in-memory")]
+        SignalExporter::InMemory => AnySpanExporter::InMemory(Default::default()),
+    }))
+}
+
+/// Metric exporter for `exporter_metrics`, None when metrics are disabled or
+/// OTLP has no endpoint.
+pub(crate) fn metric_exporter(
+    params: &OtelParams,
+) -> Result<Option<AnyMetricExporter>, Box<dyn Error + Send + Sync>> {
+    if !params.metrics_enabled {
+        return Ok(None);
+    }
+    Ok(Some(match params.exporter_metrics {
+        SignalExporter::Otlp => {
+            let Some(endpoint) = params.metrics_endpoint() else {
+                return Ok(None);
+            };
+            AnyMetricExporter::Otlp(otlp::metric_exporter(
+                params.protocol_metrics,
+                endpoint,
+                params,
+            )?)
+        }
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::Stdout => AnyMetricExporter::Stdout(Default::default()),
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::StdoutOtlpJson => AnyMetricExporter::OtlpJson(OtlpJsonMetricExporter::new(
+            std::io::stdout(),
+            otlp::resolve_temporality(params.metric_temporality),
+        )),
+        #[cfg(feature = "otel-in-memory")]
+        SignalExporter::InMemory => AnyMetricExporter::InMemory(Default::default()),
+    }))
+}
+
+/// Log exporter for `exporter_logs`, None when logs are disabled or OTLP has
+/// no endpoint.
+pub(crate) fn log_exporter(
+    params: &OtelParams,
+) -> Result<Option<AnyLogExporter>, Box<dyn Error + Send + Sync>> {
+    if !params.logs_enabled {
+        return Ok(None);
+    }
+    Ok(Some(match params.exporter_logs {
+        SignalExporter::Otlp => {
+            let Some(endpoint) = params.logs_endpoint() else {
+                return Ok(None);
+            };
+            AnyLogExporter::Otlp(otlp::log_exporter(params.protocol_logs, endpoint, params)?)
+        }
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::Stdout => AnyLogExporter::Stdout(Default::default()),
+        #[cfg(feature = "otel-stdout")]
+        SignalExporter::StdoutOtlpJson => {
+            AnyLogExporter::OtlpJson(OtlpJsonLogExporter::new(std::io::stdout()))
+        }
+        #[cfg(feature = "otel-in-memory")]
+        SignalExporter::InMemory => AnyLogExporter::InMemory(Default::default()),
+    }))
+}
+
+#[cfg(feature = "otel-in-memory")]
+impl AnySpanExporter {
+    pub(crate) fn in_memory(&self) -> Option<InMemorySpanExporter> {
+        match self {
+            Self::InMemory(exporter) => Some(exporter.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "otel-in-memory")]
+impl AnyMetricExporter {
+    pub(crate) fn in_memory(&self) -> Option<InMemoryMetricExporter> {
+        match self {
+            Self::InMemory(exporter) => Some(exporter.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "otel-in-memory")]
+impl AnyLogExporter {
+    pub(crate) fn in_memory(&self) -> Option<InMemoryLogExporter> {
+        match self {
+            Self::InMemory(exporter) => Some(exporter.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl SpanExporter for AnySpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.export(batch).await)
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown())
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.force_flush())
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        dispatch!(self, exporter => exporter.set_resource(resource))
+    }
+}
+
+impl PushMetricExporter for AnyMetricExporter {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.export(metrics).await)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.force_flush())
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown())
+    }
+
+    fn temporality(&self) -> Temporality {
+        dispatch!(self, exporter => exporter.temporality())
+    }
+}
+
+impl LogExporter for AnyLogExporter {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.export(batch).await)
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown_with_timeout(timeout))
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        dispatch!(self, exporter => exporter.shutdown())
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        dispatch!(self, exporter => exporter.set_resource(resource))
+    }
+}
+
+/// Shared writer of the OTLP-JSON exporters, one request per line.
+#[cfg(feature = "otel-stdout")]
+struct JsonLines(Mutex<Box<dyn Write + Send>>);
+
+#[cfg(feature = "otel-stdout")]
+impl JsonLines {
+    fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Mutex::new(Box::new(writer)))
+    }
+
+    fn write(&self, json: serde_json::Result<Vec<u8>>) -> OTelSdkResult {
+        let mut line = json.map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+        line.push(b'\n');
+        let mut writer = self
+            .0
+            .lock()
+            .map_err(|_| OTelSdkError::InternalFailure("writer lock poisoned".into()))?;
+        writer
+            .write_all(&line)
+            .and_then(|_| writer.flush())
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+impl std::fmt::Debug for JsonLines {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("JsonLines")
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+#[derive(Debug)]
+pub(crate) struct OtlpJsonSpanExporter {
+    resource: opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema,
+    writer: JsonLines,
+}
+
+#[cfg(feature = "otel-stdout")]
+impl OtlpJsonSpanExporter {
+    pub(crate) fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            resource: Default::default(),
+            writer: JsonLines::new(writer),
+        }
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+impl SpanExporter for OtlpJsonSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+        use opentelemetry_proto::transform::trace::tonic::group_spans_by_resource_and_scope;
+
+        let request = ExportTraceServiceRequest {
+            resource_spans: group_spans_by_resource_and_scope(batch, &self.resource),
+        };
+        self.writer.write(serde_json::to_vec(&request))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+#[derive(Debug)]
+pub(crate) struct OtlpJsonMetricExporter {
+    writer: JsonLines,
+    temporality: Temporality,
+}
+
+#[cfg(feature = "otel-stdout")]
+impl OtlpJsonMetricExporter {
+    pub(crate) fn new(writer: impl Write + Send + 'static, temporality: Temporality) -> Self {
+        Self {
+            writer: JsonLines::new(writer),
+            temporality,
+        }
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+impl PushMetricExporter for OtlpJsonMetricExporter {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+
+        self.writer
+            .write(serde_json::to_vec(&ExportMetricsServiceRequest::from(
+                metrics,
+            )))
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+#[derive(Debug)]
+pub(crate) struct OtlpJsonLogExporter {
+    resource: opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema,
+    writer: JsonLines,
+}
+
+#[cfg(feature = "otel-stdout")]
+impl OtlpJsonLogExporter {
+    pub(crate) fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            resource: Default::default(),
+            writer: JsonLines::new(writer),
+        }
+    }
+}
+
+#[cfg(feature = "otel-stdout")]
+impl LogExporter for OtlpJsonLogExporter {
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+        use opentelemetry_proto::transform::logs::tonic::group_logs_by_resource_and_scope;
+
+        let request = ExportLogsServiceRequest {
+            resource_logs: group_logs_by_resource_and_scope(batch, &self.resource),
+        };
+        self.writer.write(serde_json::to_vec(&request))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn sinks_follow_built_exporters() {
+        use super::sinks;
+        use crate::OtelParams;
+
+        assert!(sinks(&OtelParams::default()).is_empty());
+        let mut params = OtelParams::local("svc".into(), "1.0.0".into());
+        assert_eq!(sinks(&params), ["otlp"]);
+        params.traces_enabled = false;
+        params.metrics_enabled = false;
+        params.logs_enabled = false;
+        assert!(sinks(&params).is_empty());
+
+        #[cfg(feature = "otel-stdout")]
+        {
+            let mut params = OtelParams::default().with_exporter(super::SignalExporter::Stdout);
+            assert_eq!(sinks(&params), ["stdout"]);
+            params.exporter_logs = super::SignalExporter::Otlp;
+            params.endpoint_logs = Some("http://localhost:4318/v1/logs".into());
+            assert_eq!(sinks(&params), ["stdout", "otlp"]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "otel-stdout")]
+    pub fn otlp_json_lines_are_written() {
+        use super::{OtlpJsonMetricExporter, OtlpJsonSpanExporter};
+        use crate::test::BufferWriter;
+        use opentelemetry::KeyValue;
+        use opentelemetry::metrics::MeterProvider;
+        use opentelemetry::trace::{Tracer, TracerProvider};
+        use opentelemetry_sdk::Resource;
+        use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider, Temporality};
+        use opentelemetry_sdk::trace::SdkTracerProvider;
+
+        let resource = Resource::builder().with_service_name("svc").build();
+        let spans = BufferWriter::default();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(OtlpJsonSpanExporter::new(spans.clone()))
+            .with_resource(resource.clone())
+            .build();
+        tracer_provider.tracer("test").in_span("exported", |_| {});
+
+        let metrics = BufferWriter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(
+                PeriodicReader::builder(OtlpJsonMetricExporter::new(
+                    metrics.clone(),
+                    Temporality::Delta,
+                ))
+                .build(),
+            )
+            .with_resource(resource)
+            .build();
+        let counter = meter_provider.meter("test").u64_counter("orders").build();
+        counter.add(3, &[KeyValue::new("status", "ok")]);
+        meter_provider.force_flush().unwrap();
+
+        let line = spans.contents();
+        let request: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        let resource_spans = &request["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["scopeSpans"][0]["spans"][0]["name"],
+            "exported"
+        );
+        assert!(
+            resource_spans["resource"]["attributes"]
+                .to_string()
+                .contains("service.name")
+        );
+
+        let line = metrics.contents();
+        let request: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        let metric = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+        assert_eq!(metric["name"], "orders");
+        assert_eq!(metric["sum"]["dataPoints"][0]["asInt"], 3);
+        // AGGREGATION_TEMPORALITY_DELTA
+        assert_eq!(metric["sum"]["aggregationTemporality"], 1);
+    }
+
+    #[test]
+    #[cfg(feature = "otel-in-memory")]
+    pub fn in_memory_exporters_need_no_endpoint() {
+        use super::SignalExporter;
+        use crate::OtelParams;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let params = OtelParams {
+            exporter_traces: SignalExporter::InMemory,
+            exporter_logs: SignalExporter::InMemory,
+            metrics_enabled: false,
+            service_name: "svc".into(),
+            ..Default::default()
+        };
+        let (guards, layers) =
+            crate::build_otel_layers::<tracing_subscriber::Registry>(params).unwrap();
+        assert!(guards.meter.is_none());
+        let subscriber = tracing_subscriber::registry().with(layers);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("checkout").in_scope(|| tracing::info!("order placed"));
+        });
+
+        guards.tracer.as_ref().unwrap().force_flush().unwrap();
+        guards.logger.as_ref().unwrap().force_flush().unwrap();
+        let spans = guards.in_memory.spans.as_ref().unwrap();
+        assert_eq!(spans.get_finished_spans().unwrap()[0].name, "checkout");
+        let logs = guards.in_memory.logs.as_ref().unwrap();
+        let emitted = logs.get_emitted_logs().unwrap();
+        assert_eq!(
+            emitted[0].record.body(),
+            Some(&opentelemetry::logs::AnyValue::from("order placed"))
+        );
+    }
+}
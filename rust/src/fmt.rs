@@ -0,0 +1,168 @@
+use std::io::IsTerminal;
+
+use tracing::Level;
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::{DefaultFields, Format, Full};
+use tracing_subscriber::fmt::time::{ChronoUtc, FormatTime, SystemTime, Uptime};
+
+use crate::GenericLayer;
+
+/// Timestamp format used by the stdout and file layers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogTimer {
+    /// UTC RFC 3339 with microseconds (tracing-subscriber default)
+    #[default]
+    System,
+    /// UTC RFC 3339 with nanoseconds, e.g. `2025-01-01T12:00:00.123456789Z`
+    Rfc3339Nanos,
+    /// Time elapsed since the subscriber was initialized
+    Uptime,
+    /// No timestamp at all
+    None,
+}
+
+/// Where console output goes when stdout logging is enabled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleWriter {
+    #[default]
+    Stdout,
+    Stderr,
+    /// WARN and ERROR events go to stderr, INFO and below to stdout
+    SplitByLevel,
+}
+
+/// Metadata toggles applied to the stdout and file layers.
+///
+/// Defaults mirror `tracing_subscriber::fmt::layer()`.
+#[derive(Debug, Clone)]
+pub struct FmtOptions {
+    pub timer: LogTimer,
+    pub target: bool,
+    pub level: bool,
+    pub thread_ids: bool,
+    pub thread_names: bool,
+    pub file: bool,
+    pub line_number: bool,
+}
+
+impl Default for FmtOptions {
+    fn default() -> Self {
+        Self {
+            timer: LogTimer::System,
+            target: true,
+            level: true,
+            thread_ids: false,
+            thread_names: false,
+            file: false,
+            line_number: false,
+        }
+    }
+}
+
+impl FmtOptions {
+    /// Builds a boxed fmt layer writing to `writer`.
+    ///
+    /// `ansi` of None keeps the tracing-subscriber default.
+    pub(crate) fn layer<S, W>(
+        &self,
+        writer: W,
+        json: bool,
+        ansi: Option<bool>,
+    ) -> Box<GenericLayer<S>>
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a> + Send + Sync,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        let mut layer = tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_target(self.target)
+            .with_level(self.level)
+            .with_thread_ids(self.thread_ids)
+            .with_thread_names(self.thread_names)
+            .with_file(self.file)
+            .with_line_number(self.line_number);
+        if let Some(ansi) = ansi {
+            layer = layer.with_ansi(ansi);
+        }
+
+        match self.timer {
+            LogTimer::System => finish(layer.with_timer(SystemTime), json),
+            LogTimer::Rfc3339Nanos => finish(
+                layer.with_timer(ChronoUtc::new("%Y-%m-%dT%H:%M:%S%.9fZ".to_owned())),
+                json,
+            ),
+            LogTimer::Uptime => finish(layer.with_timer(Uptime::default()), json),
+            LogTimer::None => finish(layer.without_time(), json),
+        }
+    }
+
+    /// Builds the console layers for `writer`, enabling ANSI only on streams
+    /// attached to a terminal.
+    pub(crate) fn console_layers<S>(
+        &self,
+        writer: ConsoleWriter,
+        json: bool,
+    ) -> Vec<Box<GenericLayer<S>>>
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a> + Send + Sync,
+    {
+        let stdout_ansi = use_ansi(std::io::stdout().is_terminal());
+        let stderr_ansi = use_ansi(std::io::stderr().is_terminal());
+        match writer {
+            ConsoleWriter::Stdout => vec![self.layer(std::io::stdout, json, Some(stdout_ansi))],
+            ConsoleWriter::Stderr => vec![self.layer(std::io::stderr, json, Some(stderr_ansi))],
+            ConsoleWriter::SplitByLevel => self.split_layers(
+                (std::io::stdout, stdout_ansi),
+                (std::io::stderr, stderr_ansi),
+                json,
+            ),
+        }
+    }
+
+    pub(crate) fn split_layers<S, O, E>(
+        &self,
+        (out, out_ansi): (O, bool),
+        (err, err_ansi): (E, bool),
+        json: bool,
+    ) -> Vec<Box<GenericLayer<S>>>
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a> + Send + Sync,
+        O: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+        E: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        // Spans pass through both filters so events keep their span context.
+        let out_filter = filter_fn(|meta| !meta.is_event() || *meta.level() > Level::WARN);
+        let err_filter = filter_fn(|meta| !meta.is_event() || *meta.level() <= Level::WARN);
+        vec![
+            self.layer(out, json, Some(out_ansi))
+                .with_filter(out_filter)
+                .boxed(),
+            self.layer(err, json, Some(err_ansi))
+                .with_filter(err_filter)
+                .boxed(),
+        ]
+    }
+}
+
+/// Honors the `NO_COLOR` convention on top of terminal detection.
+fn use_ansi(is_terminal: bool) -> bool {
+    is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+fn finish<S, T, W>(
+    layer: tracing_subscriber::fmt::Layer<S, DefaultFields, Format<Full, T>, W>,
+    json: bool,
+) -> Box<GenericLayer<S>>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a> + Send + Sync,
+    T: FormatTime + Send + Sync + 'static,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    if json {
+        layer.json().boxed()
+    } else {
+        layer.boxed()
+    }
+}
@@ -1,4 +1,42 @@
+#[cfg(feature = "otel")]
+pub mod batch;
+#[cfg(feature = "otel-http-client")]
+pub mod client;
+#[cfg(feature = "otel")]
+pub mod error;
+#[cfg(feature = "otel")]
+pub mod exporter;
+pub mod fmt;
+#[cfg(any(feature = "otel-axum", feature = "otel-http-client"))]
+mod method;
 pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otlp;
+#[cfg(feature = "otel")]
+mod policy;
+#[cfg(feature = "otel")]
+mod process;
+#[cfg(feature = "otel")]
+pub mod propagation;
+#[cfg(feature = "otel")]
+pub mod resource;
+#[cfg(feature = "otel-tokio")]
+mod runtime;
+#[cfg(feature = "otel")]
+pub mod sampler;
+#[cfg(feature = "otel-prometheus")]
+pub mod scrape;
+#[cfg(feature = "otel-axum")]
+pub mod server;
+mod startup;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+#[cfg(feature = "otel")]
+pub mod tail_sampling;
+#[cfg(feature = "otel")]
+mod url;
+#[cfg(feature = "otel")]
+pub mod views;
 
 use std::error::Error;
 use std::fs::File;
@@ -14,14 +52,10 @@ use tracing_subscriber::util::SubscriberInitExt;
 #[cfg(feature = "otel")]
 use opentelemetry::trace::TracerProvider;
 #[cfg(feature = "otel")]
-use opentelemetry_otlp::WithExportConfig;
-#[cfg(feature = "otel")]
 use opentelemetry_sdk::logs::SdkLoggerProvider;
 #[cfg(feature = "otel")]
 use opentelemetry_sdk::metrics::SdkMeterProvider;
 #[cfg(feature = "otel")]
-use opentelemetry_sdk::propagation::TraceContextPropagator;
-#[cfg(feature = "otel")]
 use opentelemetry_sdk::trace::SdkTracerProvider;
 
 #[cfg(feature = "otel")]
@@ -34,6 +68,8 @@ pub use opentelemetry_otlp;
 pub use opentelemetry_sdk;
 #[cfg(feature = "otel")]
 pub use opentelemetry_semantic_conventions;
+#[cfg(feature = "otel-prometheus")]
+pub use prometheus;
 
 #[cfg(feature = "db")]
 pub use sqlx;
@@ -43,8 +79,39 @@ pub use utoipa;
 #[cfg(feature = "openapi")]
 pub use utoipa_axum;
 
+#[cfg(feature = "otel")]
+pub use batch::BatchSettings;
+#[cfg(feature = "otel-http-client")]
+pub use client::{HttpClientLayer, HttpClientService};
+#[cfg(feature = "otel")]
+pub use error::{DynResultErrorExt, ResultErrorExt, SpanErrorExt};
+#[cfg(feature = "otel-in-memory")]
+pub use exporter::InMemoryExporters;
+#[cfg(feature = "otel")]
+pub use exporter::SignalExporter;
+pub use fmt::{ConsoleWriter, FmtOptions, LogTimer};
 #[cfg(feature = "otel")]
 pub use metrics::{HttpRequestMetrics, IntoOtelAttributes, MetricsHelper};
+#[cfg(feature = "otel")]
+pub use otlp::{OtlpProtocol, OtlpTls};
+#[cfg(feature = "otel")]
+pub use propagation::PropagationFormat;
+#[cfg(feature = "otel-http")]
+pub use propagation::{extract_context, inject_context, set_parent_from_headers};
+#[cfg(feature = "otel")]
+pub use resource::ResourceDetectors;
+#[cfg(feature = "otel")]
+pub use sampler::{SamplerConfig, SamplingRule};
+#[cfg(feature = "otel-prometheus")]
+pub use scrape::{metrics_handler, metrics_router};
+#[cfg(feature = "otel-axum")]
+pub use server::{HttpServerLayer, HttpServerService};
+#[cfg(feature = "syslog")]
+pub use syslog::{SyslogFacility, SyslogLayer, SyslogParams, SyslogTransport};
+#[cfg(feature = "otel")]
+pub use tail_sampling::{TailSamplingConfig, TailSamplingProcessor};
+#[cfg(feature = "otel")]
+pub use views::{ExponentialHistogram, MetricView};
 
 pub use tracing;
 pub use tracing_subscriber;
@@ -55,6 +122,12 @@ pub struct OtelGuards {
     pub tracer: Option<SdkTracerProvider>,
     pub meter: Option<SdkMeterProvider>,
     pub logger: Option<SdkLoggerProvider>,
+    /// Exporters of the signals using `SignalExporter::InMemory`
+    #[cfg(feature = "otel-in-memory")]
+    pub in_memory: InMemoryExporters,
+    /// Registry scraped by `metrics_handler`, set when `OtelParams::prometheus` is on
+    #[cfg(feature = "otel-prometheus")]
+    pub prometheus: Option<prometheus::Registry>,
 }
 
 #[cfg(feature = "otel")]
@@ -84,37 +157,196 @@ pub struct TracingGuards {
 }
 
 #[cfg(feature = "otel")]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct OtelParams {
+    /// Base OTLP endpoint, e.g. `http://localhost:4318`. Over HTTP the signal
+    /// path (`/v1/traces`, ...) is appended, over gRPC it is used as-is.
+    pub endpoint: Option<String>,
+    /// Per-signal endpoints are used verbatim and take precedence over `endpoint`
     pub endpoint_traces: Option<String>,
     pub endpoint_metrics: Option<String>,
     pub endpoint_logs: Option<String>,
+    pub traces_enabled: bool,
+    pub metrics_enabled: bool,
+    pub logs_enabled: bool,
+    pub protocol_traces: OtlpProtocol,
+    pub protocol_metrics: OtlpProtocol,
+    pub protocol_logs: OtlpProtocol,
+    /// Stdout and in-memory exporters need no endpoint
+    pub exporter_traces: SignalExporter,
+    pub exporter_metrics: SignalExporter,
+    pub exporter_logs: SignalExporter,
+    /// Adds a Prometheus reader next to the periodic metric exporter, needs
+    /// the `otel-prometheus` feature
+    pub prometheus: bool,
+    /// Histogram boundaries, attribute filters, renames and dropped instruments
+    pub metric_views: Vec<MetricView>,
+    /// Falls back to `OTEL_EXPORTER_OTLP_METRICS_TEMPORALITY_PREFERENCE`, then
+    /// cumulative. Applies to the OTLP and OTLP-JSON metric exporters.
+    pub metric_temporality: Option<opentelemetry_sdk::metrics::Temporality>,
+    /// Base-2 exponential aggregation for every histogram without a view
+    /// setting boundaries
+    pub exponential_histograms: Option<ExponentialHistogram>,
+    /// Distinct attribute sets per instrument before new ones are folded into
+    /// the `otel.metric.overflow` series, `MetricView::cardinality_limit`
+    /// overrides it per instrument
+    pub cardinality_limit: Option<usize>,
+    /// Extra headers (gRPC metadata) sent with every export, e.g. API keys
+    pub headers: Vec<(String, String)>,
+    /// Gzip needs the `otel-gzip` feature (implied by `otel-grpc` for gRPC)
+    pub compression: Option<opentelemetry_otlp::Compression>,
+    /// Per-export request timeout, SDK default is 10s
+    pub export_timeout: Option<std::time::Duration>,
+    pub tls: Option<OtlpTls>,
+    /// Falls back to `OTEL_TRACES_SAMPLER`, then `ParentBased(AlwaysOn)`
+    pub sampler: Option<SamplerConfig>,
+    /// Buffers spans per trace and keeps errors and slow traces
+    pub tail_sampling: Option<TailSamplingConfig>,
+    /// Queue and batch sizes of the span and log batch processors
+    pub batch: BatchSettings,
+    /// Falls back to `OTEL_PROPAGATORS`, then W3C trace context only
+    pub propagators: Option<Vec<PropagationFormat>>,
     pub service_name: String,
     pub service_version: String,
     pub resource_attributes: Vec<opentelemetry::KeyValue>,
+    /// Opt-in host, process, container and Kubernetes attributes
+    pub resource_detectors: ResourceDetectors,
+}
+
+#[cfg(feature = "otel")]
+impl Default for OtelParams {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            endpoint_traces: None,
+            endpoint_metrics: None,
+            endpoint_logs: None,
+            traces_enabled: true,
+            metrics_enabled: true,
+            logs_enabled: true,
+            protocol_traces: OtlpProtocol::default(),
+            protocol_metrics: OtlpProtocol::default(),
+            protocol_logs: OtlpProtocol::default(),
+            exporter_traces: SignalExporter::default(),
+            exporter_metrics: SignalExporter::default(),
+            exporter_logs: SignalExporter::default(),
+            prometheus: false,
+            metric_views: Vec::new(),
+            metric_temporality: None,
+            exponential_histograms: None,
+            cardinality_limit: None,
+            headers: Vec::new(),
+            compression: None,
+            export_timeout: None,
+            tls: None,
+            sampler: None,
+            tail_sampling: None,
+            batch: BatchSettings::default(),
+            propagators: None,
+            service_name: String::new(),
+            service_version: String::new(),
+            resource_attributes: Vec::new(),
+            resource_detectors: ResourceDetectors::default(),
+        }
+    }
 }
 
 #[cfg(feature = "otel")]
 impl OtelParams {
     pub fn local(service_name: String, service_version: String) -> Self {
         Self {
-            endpoint_traces: Some("http://localhost:4318/v1/traces".into()),
-            endpoint_metrics: Some("http://localhost:4318/v1/metrics".into()),
-            endpoint_logs: Some("http://localhost:4318/v1/logs".into()),
+            endpoint: Some("http://localhost:4318".into()),
             service_name,
             service_version,
-            resource_attributes: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    /// Same protocol for traces, metrics and logs
+    pub fn with_protocol(mut self, protocol: OtlpProtocol) -> Self {
+        self.protocol_traces = protocol;
+        self.protocol_metrics = protocol;
+        self.protocol_logs = protocol;
+        self
+    }
+
+    /// Same exporter for traces, metrics and logs
+    pub fn with_exporter(mut self, exporter: SignalExporter) -> Self {
+        self.exporter_traces = exporter;
+        self.exporter_metrics = exporter;
+        self.exporter_logs = exporter;
+        self
+    }
+
+    /// Effective traces endpoint, None when traces are disabled
+    pub fn traces_endpoint(&self) -> Option<String> {
+        self.signal_endpoint(
+            self.traces_enabled,
+            &self.endpoint_traces,
+            self.protocol_traces,
+            "v1/traces",
+        )
+    }
+
+    /// Effective metrics endpoint, None when metrics are disabled
+    pub fn metrics_endpoint(&self) -> Option<String> {
+        self.signal_endpoint(
+            self.metrics_enabled,
+            &self.endpoint_metrics,
+            self.protocol_metrics,
+            "v1/metrics",
+        )
+    }
+
+    /// Effective logs endpoint, None when logs are disabled
+    pub fn logs_endpoint(&self) -> Option<String> {
+        self.signal_endpoint(
+            self.logs_enabled,
+            &self.endpoint_logs,
+            self.protocol_logs,
+            "v1/logs",
+        )
+    }
+
+    fn signal_endpoint(
+        &self,
+        enabled: bool,
+        signal_endpoint: &Option<String>,
+        protocol: OtlpProtocol,
+        path: &str,
+    ) -> Option<String> {
+        if !enabled {
+            return None;
+        }
+        if let Some(endpoint) = signal_endpoint {
+            return Some(endpoint.clone());
         }
+        let base = self.endpoint.as_ref()?;
+        #[cfg(feature = "otel-grpc")]
+        if protocol == OtlpProtocol::Grpc {
+            return Some(base.clone());
+        }
+        _ = protocol;
+        Some(format!("{}/{}", base.trim_end_matches('/'), path))
     }
 }
 
 pub struct TracingBuilder {
     json: Option<bool>,
     stdout: Option<bool>,
+    console: ConsoleWriter,
     file: Option<String>,
     env_filter: Option<EnvFilter>,
+    fmt: FmtOptions,
+    startup_event: bool,
+    #[cfg(feature = "syslog")]
+    syslog: Option<SyslogParams>,
     #[cfg(feature = "otel")]
     otel: Option<OtelParams>,
+    #[cfg(feature = "otel")]
+    process_metrics: bool,
+    #[cfg(feature = "otel-tokio")]
+    tokio_metrics: bool,
 }
 
 impl Default for TracingBuilder {
@@ -122,10 +354,19 @@ impl Default for TracingBuilder {
         Self {
             json: Some(true),
             stdout: Some(true),
+            console: ConsoleWriter::default(),
             file: None,
             env_filter: None,
+            fmt: FmtOptions::default(),
+            startup_event: true,
+            #[cfg(feature = "syslog")]
+            syslog: None,
             #[cfg(feature = "otel")]
             otel: Default::default(),
+            #[cfg(feature = "otel")]
+            process_metrics: false,
+            #[cfg(feature = "otel-tokio")]
+            tokio_metrics: false,
         }
     }
 }
@@ -140,6 +381,12 @@ impl TracingBuilder {
         self
     }
 
+    /// Stream(s) used for console output, stdout by default
+    pub fn with_console_writer(mut self, value: ConsoleWriter) -> Self {
+        self.console = value;
+        self
+    }
+
     pub fn with_env_filter(mut self, value: Option<EnvFilter>) -> Self {
         self.env_filter = value;
         self
@@ -155,12 +402,85 @@ impl TracingBuilder {
         self
     }
 
+    pub fn with_timer(mut self, value: LogTimer) -> Self {
+        self.fmt.timer = value;
+        self
+    }
+
+    pub fn with_target(mut self, value: bool) -> Self {
+        self.fmt.target = value;
+        self
+    }
+
+    pub fn with_level(mut self, value: bool) -> Self {
+        self.fmt.level = value;
+        self
+    }
+
+    pub fn with_thread_ids(mut self, value: bool) -> Self {
+        self.fmt.thread_ids = value;
+        self
+    }
+
+    pub fn with_thread_names(mut self, value: bool) -> Self {
+        self.fmt.thread_names = value;
+        self
+    }
+
+    /// Source file path of the callsite
+    pub fn with_source_file(mut self, value: bool) -> Self {
+        self.fmt.file = value;
+        self
+    }
+
+    pub fn with_line_number(mut self, value: bool) -> Self {
+        self.fmt.line_number = value;
+        self
+    }
+
+    /// Replaces all fmt layer options at once
+    pub fn with_fmt_options(mut self, value: FmtOptions) -> Self {
+        self.fmt = value;
+        self
+    }
+
+    /// Emit one INFO event summarizing the active configuration after init,
+    /// also when the filter is stricter than INFO. Enabled by default.
+    pub fn with_startup_event(mut self, value: bool) -> Self {
+        self.startup_event = value;
+        self
+    }
+
+    #[cfg(feature = "syslog")]
+    pub fn with_syslog(mut self, params: SyslogParams) -> Self {
+        self.syslog = Some(params);
+        self
+    }
+
     #[cfg(feature = "otel")]
     pub fn with_otel(mut self, otel: OtelParams) -> Self {
         self.otel = Some(otel);
         self
     }
 
+    /// Report CPU, memory, file descriptor, thread and uptime metrics of the
+    /// process on the global meter, see `MetricsHelper::process_metrics`.
+    /// Needs metrics enabled in `with_otel`.
+    #[cfg(feature = "otel")]
+    pub fn with_process_metrics(mut self, value: bool) -> Self {
+        self.process_metrics = value;
+        self
+    }
+
+    /// Report worker, task, queue and busy time metrics of the current Tokio
+    /// runtime on the global meter, see `MetricsHelper::tokio_runtime_metrics`.
+    /// `try_init` fails outside a runtime.
+    #[cfg(feature = "otel-tokio")]
+    pub fn with_tokio_runtime_metrics(mut self, value: bool) -> Self {
+        self.tokio_metrics = value;
+        self
+    }
+
     pub fn with_predefined_file(mut self) -> Self {
         self.file = Some("./log.txt".to_owned());
         self
@@ -184,70 +504,126 @@ impl TracingBuilder {
     }
 
     pub fn try_init(self) -> Result<TracingGuards, Box<dyn Error + Send + Sync>> {
-        use tracing_subscriber::Layer;
-        use tracing_subscriber::fmt::layer;
-
         let json = self.json.unwrap_or(true);
         let stdout = self.stdout.unwrap_or(true);
         #[allow(unused_mut)]
         let mut layers: Vec<Box<_>> = Vec::new();
+        let mut sinks = Vec::new();
 
-        if let Some(file) = self.file {
-            let file = File::create(&file)?;
-            let layer = layer().with_ansi(false).with_writer(file);
-            if json {
-                layers.push(layer.json().boxed());
-            } else {
-                layers.push(layer.boxed());
-            }
+        if let Some(file) = &self.file {
+            let file = File::create(file)?;
+            layers.push(self.fmt.layer(file, json, Some(false)));
+            sinks.push("file");
         };
 
         if stdout {
-            let layer = layer();
-            if json {
-                layers.push(layer.json().boxed());
-            } else {
-                layers.push(layer.boxed());
-            }
+            layers.extend(self.fmt.console_layers(self.console, json));
+            sinks.push("console");
         }
 
+        #[cfg(feature = "syslog")]
+        if let Some(params) = self.syslog {
+            layers.push(SyslogLayer::new(params)?.boxed());
+            sinks.push("syslog");
+        }
+
+        #[allow(unused_mut)]
         let mut tracing_guards = TracingGuards::default();
         #[cfg(feature = "otel")]
+        let mut otel_summary = None;
+        #[cfg(feature = "otel")]
+        let mut skipped_propagators = Vec::new();
+        #[cfg(feature = "otel-tokio")]
+        let runtime = if self.tokio_metrics {
+            Some(tokio::runtime::Handle::try_current().map_err(
+                |_| "TracingBuilder::with_tokio_runtime_metrics must be called in a Tokio runtime",
+            )?)
+        } else {
+            None
+        };
+        #[cfg(feature = "otel")]
         {
-            if let Some(otel) = self.otel {
+            if let Some(mut otel) = self.otel {
                 use tracing_subscriber::Registry;
 
+                // Resolved here so skipped entries are warned about once the
+                // subscriber is installed.
+                if otel.propagators.is_none()
+                    && let Ok(value) = std::env::var("OTEL_PROPAGATORS")
+                {
+                    let (formats, skipped) = PropagationFormat::parse_list(&value);
+                    otel.propagators = Some(formats);
+                    skipped_propagators = skipped;
+                }
+                otel_summary = Some(startup::OtelSummary::new(&otel));
+                let otel_sinks = exporter::sinks(&otel);
+                let prometheus = otel.metrics_enabled && otel.prometheus;
                 let (guard, otel_layers) = build_otel_layers::<Registry>(otel)?;
                 tracing_guards.otel = guard;
                 layers.extend(otel_layers);
+                if tracing_guards.otel.meter.is_some() {
+                    let meter = opentelemetry::global::meter("internal-utils");
+                    if self.process_metrics {
+                        MetricsHelper::process_metrics(&meter);
+                    }
+                    #[cfg(feature = "otel-tokio")]
+                    if let Some(handle) = runtime {
+                        MetricsHelper::tokio_runtime_metrics(&meter, &handle);
+                    }
+                }
+                sinks.extend(otel_sinks);
+                if prometheus {
+                    sinks.push("prometheus");
+                }
             }
         }
 
+        let env_filter = self.env_filter.unwrap_or_else(EnvFilter::from_default_env);
+        let filter = env_filter.to_string();
+        let env_filter = if self.startup_event {
+            startup::enable(env_filter)
+        } else {
+            env_filter
+        };
         tracing_subscriber::registry()
             .with(layers)
-            .with(
-                self.env_filter
-                    .unwrap_or_else(|| EnvFilter::from_default_env()),
-            )
+            .with(env_filter)
             .try_init()?;
 
+        #[cfg(feature = "otel")]
+        PropagationFormat::warn_skipped(&skipped_propagators);
+        if self.startup_event {
+            startup::StartupSummary {
+                sinks,
+                json,
+                console: stdout.then_some(self.console),
+                file: self.file,
+                filter,
+                #[cfg(feature = "otel")]
+                otel: otel_summary,
+            }
+            .emit();
+        }
+
         Ok(tracing_guards)
     }
 }
 
 pub type GenericLayer<S> = dyn Layer<S> + Send + Sync;
+pub type BoxedLayers<S> = Vec<Box<GenericLayer<S>>>;
 
 #[cfg(feature = "otel")]
 pub fn build_otel_layers<
     S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a> + Send + Sync,
 >(
     params: OtelParams,
-) -> Result<(OtelGuards, Vec<Box<GenericLayer<S>>>), Box<dyn Error + Send + Sync>> {
+) -> Result<(OtelGuards, BoxedLayers<S>), Box<dyn Error + Send + Sync>> {
     use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
-    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let propagators = PropagationFormat::resolve(params.propagators.as_deref());
+    opentelemetry::global::set_text_map_propagator(PropagationFormat::build(&propagators));
 
     let mut guards = OtelGuards::default();
-    let mut layers: Vec<Box<GenericLayer<S>>> = Vec::with_capacity(3);
+    let mut layers: BoxedLayers<S> = Vec::with_capacity(3);
 
     // Trace
     let mut attributes = vec![
@@ -255,54 +631,65 @@ pub fn build_otel_layers<
         opentelemetry::KeyValue::new(SERVICE_VERSION, params.service_version.clone()),
     ];
     attributes.extend(params.resource_attributes.clone());
+    // Detected values lose against OTEL_RESOURCE_ATTRIBUTES, which is applied
+    // again on top, and against explicit attributes.
     let resource = opentelemetry_sdk::Resource::builder()
+        .with_attributes(params.resource_detectors.detect())
+        .with_detectors(&[Box::new(
+            opentelemetry_sdk::resource::EnvResourceDetector::new(),
+        )])
         .with_attributes(attributes)
         .build();
 
-    if let Some(endpoint) = params.endpoint_traces {
+    if let Some(exporter) = exporter::span_exporter(&params)? {
         use tracing_subscriber::Layer;
 
-        let exporter = opentelemetry_otlp::SpanExporter::builder()
-            .with_http()
-            .with_endpoint(endpoint)
-            .build()?;
+        #[cfg(feature = "otel-in-memory")]
+        {
+            guards.in_memory.spans = exporter.in_memory();
+        }
         // Create a tracer provider with the exporter
-        let tracer_provider: SdkTracerProvider =
-            opentelemetry_sdk::trace::SdkTracerProvider::builder()
-                .with_batch_exporter(exporter)
-                .with_resource(resource.clone())
-                .build();
-        let tracer: opentelemetry_sdk::trace::Tracer = tracer_provider.tracer(params.service_name);
+        let batch = batch::QueuedSpanProcessor::new(exporter, &params.batch)?;
+        let builder = SdkTracerProvider::builder();
+        let builder = match params.tail_sampling.clone() {
+            Some(config) => builder.with_span_processor(TailSamplingProcessor::new(batch, config)),
+            None => builder.with_span_processor(batch),
+        };
+        let tracer_provider: SdkTracerProvider = builder
+            .with_sampler(sampler::BoxedSampler(
+                SamplerConfig::resolve(params.sampler.as_ref()).build(),
+            ))
+            .with_resource(resource.clone())
+            .build();
+        let tracer: opentelemetry_sdk::trace::Tracer =
+            tracer_provider.tracer(params.service_name.clone());
         opentelemetry::global::set_tracer_provider(tracer_provider.clone());
         layers.push(tracing_opentelemetry::layer().with_tracer(tracer).boxed());
         guards.tracer = Some(tracer_provider);
     }
 
-    if let Some(endpoint) = params.endpoint_metrics {
-        // Metrics
-        let exporter = opentelemetry_otlp::MetricExporter::builder()
-            .with_http()
-            .with_endpoint(endpoint)
-            .build()?;
-        let meter_provider: SdkMeterProvider = SdkMeterProvider::builder()
-            .with_resource(resource.clone())
-            .with_periodic_exporter(exporter)
-            .build();
-        opentelemetry::global::set_meter_provider(meter_provider.clone());
+    // Metrics
+    if let Some(meter_provider) = build_meter_provider(&params, resource.clone(), &mut guards)? {
+        match policy::AttributePolicy::new(&params.metric_views, params.cardinality_limit) {
+            Some(policy) => {
+                opentelemetry::global::set_meter_provider(policy.wrap(meter_provider.clone()))
+            }
+            None => opentelemetry::global::set_meter_provider(meter_provider.clone()),
+        }
         guards.meter = Some(meter_provider);
     }
 
-    if let Some(endpoint) = params.endpoint_logs {
+    if let Some(exporter) = exporter::log_exporter(&params)? {
         // Logs
 
         use tracing_subscriber::Layer;
-        let exporter = opentelemetry_otlp::LogExporter::builder()
-            .with_http()
-            .with_endpoint(endpoint)
-            .build()?;
+        #[cfg(feature = "otel-in-memory")]
+        {
+            guards.in_memory.logs = exporter.in_memory();
+        }
         let log_provider: SdkLoggerProvider = SdkLoggerProvider::builder()
             .with_resource(resource.clone())
-            .with_batch_exporter(exporter)
+            .with_log_processor(batch::QueuedLogProcessor::new(exporter, &params.batch))
             .build();
         let a =
             opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge::new(&log_provider)
@@ -314,6 +701,47 @@ pub fn build_otel_layers<
     Ok((guards, layers))
 }
 
+/// Meter provider with the periodic exporter and the Prometheus reader, None
+/// when metrics are disabled or have nowhere to go.
+#[cfg(feature = "otel")]
+pub(crate) fn build_meter_provider(
+    params: &OtelParams,
+    resource: opentelemetry_sdk::Resource,
+    guards: &mut OtelGuards,
+) -> Result<Option<SdkMeterProvider>, Box<dyn Error + Send + Sync>> {
+    let exporter = exporter::metric_exporter(params)?;
+    let prometheus = params.metrics_enabled && params.prometheus;
+    if exporter.is_none() && !prometheus {
+        return Ok(None);
+    }
+
+    let mut builder = views::register(
+        SdkMeterProvider::builder().with_resource(resource),
+        &params.metric_views,
+        params.exponential_histograms,
+        params.cardinality_limit,
+    )?;
+    if let Some(exporter) = exporter {
+        #[cfg(feature = "otel-in-memory")]
+        {
+            guards.in_memory.metrics = exporter.in_memory();
+        }
+        builder = builder.with_periodic_exporter(exporter);
+    }
+    if prometheus {
+        #[cfg(feature = "otel-prometheus")]
+        {
+            let registry = prometheus::Registry::new();
+            builder = builder.with_reader(scrape::reader(&registry)?);
+            guards.prometheus = Some(registry);
+        }
+        #[cfg(not(feature = "otel-prometheus"))]
+        return Err("OtelParams::prometheus requires the `otel-prometheus` feature".into());
+    }
+    _ = guards;
+    Ok(Some(builder.build()))
+}
+
 #[cfg(feature = "otel")]
 pub fn otel_meter(service_name: &'static str) -> opentelemetry::metrics::Meter {
     opentelemetry::global::meter(service_name)
@@ -338,10 +766,60 @@ impl Db {
 
 #[cfg(test)]
 pub mod test {
-    use crate::{TracingBuilder, debug, error, info, otel_meter, trace, warn};
+    use crate::{
+        FmtOptions, LogTimer, TracingBuilder, debug, error, info, otel_meter, trace, warn,
+    };
+    use std::sync::{Arc, Mutex};
     use std::{thread::sleep, time::Duration};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    pub struct BufferWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl BufferWriter {
+        pub fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl std::io::Write for BufferWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Latest cumulative value of a u64 counter, summed over attribute sets.
+    #[cfg(feature = "otel")]
+    pub fn metric_sum(
+        exporter: &opentelemetry_sdk::metrics::InMemoryMetricExporter,
+        name: &str,
+    ) -> u64 {
+        use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+
+        let snapshots = exporter.get_finished_metrics().unwrap();
+        let Some(latest) = snapshots.last() else {
+            return 0;
+        };
+        latest
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+                    sum.data_points().map(|point| point.value()).sum()
+                }
+                _ => 0,
+            })
+            .sum()
+    }
 
     #[test]
+    #[ignore = "requires an OTLP collector on localhost:4318"]
     pub fn tracing_works() {
         let _guards = TracingBuilder::new()
             .with_predefined_file()
@@ -390,6 +868,7 @@ pub mod test {
     }
 
     #[test]
+    #[ignore = "requires an OTLP collector on localhost:4318"]
     pub fn use_external_metrics() {
         let _guards = TracingBuilder::new()
             .with_predefined_file()
@@ -436,4 +915,84 @@ pub mod test {
         tracing::event!(target: "my-service", tracing::Level::INFO, reason, code_debug = ?code, code_display = %code, "HTTP Fetch Failed");
         tracing::info!(target: "my-service", reason, code_debug = ?code, code_display = %code, "HTTP Fetch Failed");
     }
+
+    #[test]
+    pub fn fmt_options_are_applied() {
+        let buffer = BufferWriter::default();
+        let writer = buffer.clone();
+        let options = FmtOptions {
+            timer: LogTimer::Rfc3339Nanos,
+            target: false,
+            thread_names: true,
+            file: true,
+            line_number: true,
+            ..Default::default()
+        };
+        let layer = options.layer(move || writer.clone(), true, Some(false));
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || info!("fmt options"));
+
+        let output = buffer.contents();
+        assert!(output.contains(r#""threadName":"test::fmt_options_are_applied""#));
+        assert!(output.contains(r#""filename":"src/lib.rs""#));
+        assert!(output.contains(r#""line_number":"#));
+        assert!(!output.contains(r#""target":"#));
+
+        let timestamp = output
+            .split(r#""timestamp":""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        let fraction = timestamp.split('.').nth(1).unwrap();
+        assert_eq!(fraction.len(), "123456789Z".len());
+    }
+
+    #[test]
+    pub fn split_writer_routes_by_level() {
+        let out = BufferWriter::default();
+        let err = BufferWriter::default();
+        let (out_writer, err_writer) = (out.clone(), err.clone());
+        let layers = FmtOptions::default().split_layers(
+            (move || out_writer.clone(), false),
+            (move || err_writer.clone(), false),
+            false,
+        );
+        let subscriber = tracing_subscriber::registry().with(layers);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::warn_span!("request");
+            let _g = span.enter();
+            debug!("debug event");
+            info!("info event");
+            warn!("warn event");
+            error!("error event");
+        });
+
+        let (out, err) = (out.contents(), err.contents());
+        assert!(out.contains("info event") && out.contains("request"));
+        assert!(!out.contains("warn event") && !out.contains("error event"));
+        assert!(err.contains("warn event") && err.contains("error event"));
+        assert!(!err.contains("info event"));
+    }
+
+    #[test]
+    pub fn otel_endpoints_are_derived() {
+        let mut params = crate::OtelParams::local("svc".into(), "1.0.0".into());
+        assert_eq!(
+            params.traces_endpoint().as_deref(),
+            Some("http://localhost:4318/v1/traces")
+        );
+
+        params.endpoint = Some("https://collector:4318/otlp/".into());
+        params.endpoint_logs = Some("https://logs:4318/custom".into());
+        params.metrics_enabled = false;
+        assert_eq!(
+            params.traces_endpoint().as_deref(),
+            Some("https://collector:4318/otlp/v1/traces")
+        );
+        assert_eq!(params.metrics_endpoint(), None);
+        assert_eq!(
+            params.logs_endpoint().as_deref(),
+            Some("https://logs:4318/custom")
+        );
+    }
 }
@@ -0,0 +1,35 @@
+/// `http.request.method` of requests whose method is not in `KNOWN`.
+pub(crate) const OTHER: &str = "_OTHER";
+
+const KNOWN: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// `http.request.method` value for `method`, the methods of the HTTP semantic
+/// conventions as-is and `_OTHER` otherwise. Extension methods are picked by
+/// the peer and would otherwise add span names and metric series without
+/// bound, record the raw value as `http.request.method_original` instead.
+pub(crate) fn normalize(method: &http::Method) -> &'static str {
+    KNOWN
+        .into_iter()
+        .find(|known| *known == method.as_str())
+        .unwrap_or(OTHER)
+}
+
+/// Method part of the span name, `HTTP` stands in for `_OTHER`.
+pub(crate) fn span_name(method: &'static str) -> &'static str {
+    if method == OTHER { "HTTP" } else { method }
+}
+
+#[cfg(test)]
+mod test {
+    use super::normalize;
+
+    #[test]
+    pub fn unknown_methods_are_folded() {
+        assert_eq!(normalize(&http::Method::PATCH), "PATCH");
+        assert_eq!(normalize(&"FOO1".parse().unwrap()), "_OTHER");
+        // Methods are case-sensitive.
+        assert_eq!(normalize(&"get".parse().unwrap()), "_OTHER");
+    }
+}
@@ -27,6 +27,17 @@ impl MetricsHelper {
             .build()
     }
 
+    pub fn http_client_request_duration(meter: &opentelemetry::metrics::Meter) -> Histogram<u64> {
+        meter
+            .u64_histogram("http.client.request.duration")
+            .with_description("Outbound HTTP request duration")
+            .with_unit("ms")
+            .with_boundaries(vec![
+                5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+            ])
+            .build()
+    }
+
     pub fn db_operation_counter(meter: &opentelemetry::metrics::Meter) -> Counter<u64> {
         meter
             .u64_counter("db.client.operation.total")
@@ -45,10 +56,28 @@ impl MetricsHelper {
             ])
             .build()
     }
+
+    /// Observable CPU time, memory, open file descriptor, thread count and
+    /// uptime instruments for the current process, read from `/proc` on Linux.
+    pub fn process_metrics(meter: &opentelemetry::metrics::Meter) {
+        crate::process::register(meter);
+    }
+
+    /// Observable worker, alive task, global queue depth and per-worker busy
+    /// time instruments for the runtime of `handle`. Budget-forced yields are
+    /// reported when built with `--cfg tokio_unstable`.
+    #[cfg(feature = "otel-tokio")]
+    pub fn tokio_runtime_metrics(
+        meter: &opentelemetry::metrics::Meter,
+        handle: &tokio::runtime::Handle,
+    ) {
+        crate::runtime::register(meter, handle.clone());
+    }
 }
 
 #[cfg(feature = "otel")]
 pub trait IntoOtelAttributes {
+    #[allow(clippy::wrong_self_convention)]
     fn into_attributes(&self) -> Vec<KeyValue>;
 }
 
@@ -62,6 +91,12 @@ pub struct HttpRequestMetrics {
     pub duration_ms: Option<u64>,
 }
 
+impl Default for HttpRequestMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HttpRequestMetrics {
     pub fn new() -> Self {
         Self {
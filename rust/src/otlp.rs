@@ -0,0 +1,496 @@
+use std::error::Error;
+#[cfg(any(feature = "otel-tls", feature = "otel-grpc"))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(feature = "otel-grpc")]
+use opentelemetry_otlp::WithTonicConfig;
+use opentelemetry_otlp::{
+    LogExporter, MetricExporter, Protocol, SpanExporter, WithExportConfig, WithHttpConfig,
+};
+use opentelemetry_sdk::metrics::Temporality;
+
+use crate::OtelParams;
+
+/// OTLP transport used by a single signal exporter.
+///
+/// `HttpJson` and `Grpc` require the `otel-http-json` and `otel-grpc` features.
+/// gRPC endpoints carry no signal path (e.g. `http://localhost:4317`) and the
+/// exporter must be built inside a Tokio runtime.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    #[default]
+    HttpProtobuf,
+    #[cfg(feature = "otel-http-json")]
+    HttpJson,
+    #[cfg(feature = "otel-grpc")]
+    Grpc,
+}
+
+impl FromStr for OtlpProtocol {
+    type Err = String;
+
+    /// Accepts the protocol names of the OTLP exporter spec (`http/protobuf`,
+    /// `http/json`, `grpc`). `OTEL_EXPORTER_OTLP_PROTOCOL` is not read, parse
+    /// it into `OtelParams::with_protocol` to honor it.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "http/protobuf" => Ok(Self::HttpProtobuf),
+            #[cfg(feature = "otel-http-json")]
+            "http/json" => Ok(Self::HttpJson),
+            #[cfg(feature = "otel-grpc")]
+            "grpc" => Ok(Self::Grpc),
+            other => Err(format!("unsupported OTLP protocol: {other}")),
+        }
+    }
+}
+
+/// TLS material for the OTLP exporters, all files PEM encoded.
+///
+/// HTTP exporters require the `otel-tls` feature, gRPC exporters `otel-grpc`.
+#[derive(Debug, Default, Clone)]
+pub struct OtlpTls {
+    /// CA bundle used to verify the collector certificate
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate for mTLS, requires `client_key`
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// gRPC only, overrides the domain name checked against the certificate
+    pub domain_name: Option<String>,
+}
+
+macro_rules! build_exporter {
+    ($builder:expr, $protocol:expr, $endpoint:expr, $params:expr) => {
+        match $protocol {
+            OtlpProtocol::HttpProtobuf => configure_http(
+                $builder.with_http(),
+                Protocol::HttpBinary,
+                $endpoint,
+                $params,
+            )?
+            .build()?,
+            #[cfg(feature = "otel-http-json")]
+            OtlpProtocol::HttpJson => {
+                configure_http($builder.with_http(), Protocol::HttpJson, $endpoint, $params)?
+                    .build()?
+            }
+            #[cfg(feature = "otel-grpc")]
+            OtlpProtocol::Grpc => {
+                configure_grpc($builder.with_tonic(), $endpoint, $params)?.build()?
+            }
+        }
+    };
+}
+
+pub(crate) fn span_exporter(
+    protocol: OtlpProtocol,
+    endpoint: String,
+    params: &OtelParams,
+) -> Result<SpanExporter, Box<dyn Error + Send + Sync>> {
+    Ok(build_exporter!(
+        SpanExporter::builder(),
+        protocol,
+        endpoint,
+        params
+    ))
+}
+
+pub(crate) fn metric_exporter(
+    protocol: OtlpProtocol,
+    endpoint: String,
+    params: &OtelParams,
+) -> Result<MetricExporter, Box<dyn Error + Send + Sync>> {
+    Ok(build_exporter!(
+        MetricExporter::builder().with_temporality(resolve_temporality(params.metric_temporality)),
+        protocol,
+        endpoint,
+        params
+    ))
+}
+
+pub(crate) fn log_exporter(
+    protocol: OtlpProtocol,
+    endpoint: String,
+    params: &OtelParams,
+) -> Result<LogExporter, Box<dyn Error + Send + Sync>> {
+    Ok(build_exporter!(
+        LogExporter::builder(),
+        protocol,
+        endpoint,
+        params
+    ))
+}
+
+/// Code configuration first, then
+/// `OTEL_EXPORTER_OTLP_METRICS_TEMPORALITY_PREFERENCE`, then cumulative.
+pub(crate) fn resolve_temporality(configured: Option<Temporality>) -> Temporality {
+    configured
+        .or_else(|| {
+            let value = std::env::var("OTEL_EXPORTER_OTLP_METRICS_TEMPORALITY_PREFERENCE").ok()?;
+            parse_temporality(&value)
+        })
+        .unwrap_or_default()
+}
+
+/// Accepts the spec values `cumulative`, `delta` and `lowmemory`.
+fn parse_temporality(value: &str) -> Option<Temporality> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "cumulative" => Some(Temporality::Cumulative),
+        "delta" => Some(Temporality::Delta),
+        "lowmemory" => Some(Temporality::LowMemory),
+        _ => None,
+    }
+}
+
+fn configure_http<B: WithExportConfig + WithHttpConfig>(
+    builder: B,
+    protocol: Protocol,
+    endpoint: String,
+    params: &OtelParams,
+) -> Result<B, Box<dyn Error + Send + Sync>> {
+    let mut builder = builder.with_protocol(protocol).with_endpoint(endpoint);
+    if !params.headers.is_empty() {
+        builder = builder.with_headers(params.headers.iter().cloned().collect());
+    }
+    if let Some(compression) = params.compression {
+        builder = builder.with_compression(compression);
+    }
+    if let Some(timeout) = params.export_timeout {
+        builder = builder.with_timeout(timeout);
+    }
+    if let Some(tls) = &params.tls {
+        builder = with_http_tls(builder, tls, params.export_timeout)?;
+    }
+    Ok(builder)
+}
+
+#[cfg(feature = "otel-tls")]
+fn with_http_tls<B: WithHttpConfig>(
+    exporter: B,
+    tls: &OtlpTls,
+    timeout: Option<Duration>,
+) -> Result<B, Box<dyn Error + Send + Sync>> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .timeout(timeout.unwrap_or(opentelemetry_otlp::OTEL_EXPORTER_OTLP_TIMEOUT_DEFAULT));
+    if let Some(ca) = &tls.ca_cert {
+        builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&read(ca)?)?);
+    }
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let mut pem = read(cert)?;
+            pem.extend(read(key)?);
+            builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+        }
+        (None, None) => {}
+        _ => return Err("OtlpTls needs both client_cert and client_key".into()),
+    }
+    // The blocking client owns a runtime and must not be built inside one.
+    let client = std::thread::spawn(move || builder.build())
+        .join()
+        .map_err(|_| "failed to build the OTLP HTTP client")??;
+    Ok(exporter.with_http_client(client))
+}
+
+#[cfg(not(feature = "otel-tls"))]
+fn with_http_tls<B: WithHttpConfig>(
+    _exporter: B,
+    _tls: &OtlpTls,
+    _timeout: Option<Duration>,
+) -> Result<B, Box<dyn Error + Send + Sync>> {
+    Err("TLS settings for OTLP over HTTP require the `otel-tls` feature".into())
+}
+
+#[cfg(feature = "otel-grpc")]
+fn configure_grpc<B: WithExportConfig + WithTonicConfig>(
+    builder: B,
+    endpoint: String,
+    params: &OtelParams,
+) -> Result<B, Box<dyn Error + Send + Sync>> {
+    use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+    use opentelemetry_otlp::tonic_types::transport::{Certificate, ClientTlsConfig, Identity};
+
+    let mut builder = builder
+        .with_protocol(Protocol::Grpc)
+        .with_endpoint(endpoint);
+    if !params.headers.is_empty() {
+        let mut headers = http::HeaderMap::new();
+        for (key, value) in params.headers.iter() {
+            headers.insert(
+                http::HeaderName::from_bytes(key.as_bytes())?,
+                http::HeaderValue::from_str(value)?,
+            );
+        }
+        builder = builder.with_metadata(MetadataMap::from_headers(headers));
+    }
+    if let Some(compression) = params.compression {
+        builder = builder.with_compression(compression);
+    }
+    if let Some(timeout) = params.export_timeout {
+        builder = builder.with_timeout(timeout);
+    }
+    if let Some(tls) = &params.tls {
+        let mut config = ClientTlsConfig::new().with_native_roots();
+        if let Some(ca) = &tls.ca_cert {
+            config = config.ca_certificate(Certificate::from_pem(read(ca)?));
+        }
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(cert), Some(key)) => {
+                config = config.identity(Identity::from_pem(read(cert)?, read(key)?));
+            }
+            (None, None) => {}
+            _ => return Err("OtlpTls needs both client_cert and client_key".into()),
+        }
+        if let Some(domain) = &tls.domain_name {
+            config = config.domain_name(domain.clone());
+        }
+        builder = builder.with_tls_config(config);
+    }
+    Ok(builder)
+}
+
+#[cfg(any(feature = "otel-tls", feature = "otel-grpc"))]
+fn read(path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{OtlpProtocol, OtlpTls, span_exporter};
+    use crate::OtelParams;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{Receiver, channel};
+
+    /// Request captured by [`mock_http_receiver`].
+    #[derive(Debug)]
+    pub struct CapturedRequest {
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl CapturedRequest {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Minimal OTLP/HTTP collector answering every request with 200.
+    pub fn mock_http_receiver() -> (String, Receiver<CapturedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap_or_default().to_owned();
+
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((k, v)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.push((k.to_owned(), v.to_owned()));
+                }
+                let length = headers
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, v)| v.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0u8; length];
+                reader.read_exact(&mut body).unwrap();
+
+                _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+                _ = tx.send(CapturedRequest {
+                    path,
+                    headers,
+                    body,
+                });
+            }
+        });
+        (addr, rx)
+    }
+
+    fn export_one_span(protocol: OtlpProtocol, endpoint: String, params: &OtelParams) {
+        let exporter = span_exporter(protocol, endpoint, params).unwrap();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        provider.tracer("test").in_span("exported", |_| {});
+        _ = provider.shutdown();
+    }
+
+    #[test]
+    pub fn http_protobuf_export() {
+        let (addr, rx) = mock_http_receiver();
+        export_one_span(
+            OtlpProtocol::HttpProtobuf,
+            format!("{addr}/v1/traces"),
+            &OtelParams::default(),
+        );
+
+        let request = rx.recv().unwrap();
+        assert_eq!(request.path, "/v1/traces");
+        assert_eq!(
+            request.header("content-type"),
+            Some("application/x-protobuf")
+        );
+        assert!(!request.body.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "otel-http-json")]
+    pub fn http_json_export() {
+        let (addr, rx) = mock_http_receiver();
+        export_one_span(
+            OtlpProtocol::HttpJson,
+            format!("{addr}/v1/traces"),
+            &OtelParams::default(),
+        );
+
+        let request = rx.recv().unwrap();
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        let body = String::from_utf8(request.body).unwrap();
+        assert!(body.contains("\"exported\""));
+    }
+
+    #[cfg(feature = "otel-grpc")]
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn grpc_export() {
+        use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{
+            TraceService, TraceServiceServer,
+        };
+        use opentelemetry_proto::tonic::collector::trace::v1::{
+            ExportTraceServiceRequest, ExportTraceServiceResponse,
+        };
+
+        struct MockTraceService(tokio::sync::mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+        #[tonic::async_trait]
+        impl TraceService for MockTraceService {
+            async fn export(
+                &self,
+                request: tonic::Request<ExportTraceServiceRequest>,
+            ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+                let api_key = request.metadata().get("x-api-key").cloned();
+                assert_eq!(api_key.unwrap(), "secret");
+                _ = self.0.send(request.into_inner());
+                Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+            }
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(TraceServiceServer::new(MockTraceService(tx)))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let params = OtelParams {
+            headers: vec![("x-api-key".into(), "secret".into())],
+            ..Default::default()
+        };
+        let exporter = span_exporter(OtlpProtocol::Grpc, addr, &params).unwrap();
+        // The simple processor blocks on export, keep it off the runtime workers.
+        tokio::task::spawn_blocking(move || {
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter)
+                .build();
+            provider.tracer("test").in_span("exported", |_| {});
+            _ = provider.shutdown();
+        })
+        .await
+        .unwrap();
+
+        let request = rx.recv().await.unwrap();
+        let span = &request.resource_spans[0].scope_spans[0].spans[0];
+        assert_eq!(span.name, "exported");
+    }
+
+    #[test]
+    pub fn protocol_from_env_value() {
+        assert_eq!(
+            "http/protobuf".parse::<OtlpProtocol>(),
+            Ok(OtlpProtocol::HttpProtobuf)
+        );
+        assert!("carrier-pigeon".parse::<OtlpProtocol>().is_err());
+    }
+
+    #[test]
+    pub fn headers_and_compression_are_sent() {
+        let (addr, rx) = mock_http_receiver();
+        let params = OtelParams {
+            headers: vec![("x-api-key".into(), "secret".into())],
+            #[cfg(feature = "otel-gzip")]
+            compression: Some(opentelemetry_otlp::Compression::Gzip),
+            export_timeout: Some(std::time::Duration::from_secs(2)),
+            ..Default::default()
+        };
+        export_one_span(
+            OtlpProtocol::HttpProtobuf,
+            format!("{addr}/v1/traces"),
+            &params,
+        );
+
+        let request = rx.recv().unwrap();
+        assert_eq!(request.header("x-api-key"), Some("secret"));
+        #[cfg(feature = "otel-gzip")]
+        assert_eq!(request.header("content-encoding"), Some("gzip"));
+    }
+
+    #[test]
+    pub fn metric_temporality_is_applied() {
+        use super::{metric_exporter, parse_temporality};
+        use opentelemetry_sdk::metrics::Temporality;
+        use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+
+        assert_eq!(parse_temporality("Delta"), Some(Temporality::Delta));
+        assert_eq!(parse_temporality("lowmemory"), Some(Temporality::LowMemory));
+        assert_eq!(parse_temporality("sometimes"), None);
+
+        let params = OtelParams {
+            metric_temporality: Some(Temporality::Delta),
+            ..Default::default()
+        };
+        let exporter = metric_exporter(
+            OtlpProtocol::HttpProtobuf,
+            "http://localhost:4318/v1/metrics".into(),
+            &params,
+        )
+        .unwrap();
+        assert_eq!(exporter.temporality(), Temporality::Delta);
+    }
+
+    #[test]
+    pub fn incomplete_client_identity_is_rejected() {
+        let params = OtelParams {
+            tls: Some(OtlpTls {
+                client_cert: Some("client.pem".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(
+            span_exporter(
+                OtlpProtocol::HttpProtobuf,
+                "https://collector".into(),
+                &params
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,573 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use opentelemetry::metrics::{
+    AsyncInstrument, AsyncInstrumentBuilder, Counter, Gauge, Histogram, HistogramBuilder,
+    InstrumentBuilder, InstrumentProvider, Meter, MeterProvider, ObservableCounter,
+    ObservableGauge, ObservableUpDownCounter, SyncInstrument, UpDownCounter,
+};
+use opentelemetry::{InstrumentationScope, Key, KeyValue, Value};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+
+use crate::MetricView;
+
+/// Policy per (scope, instrument) name, None when nothing applies.
+type Instruments = HashMap<(String, String), Option<Arc<InstrumentPolicy>>>;
+
+/// Attribute set recorded for an overflowing measurement, same as the SDK's.
+pub(crate) const OVERFLOW: &str = "otel.metric.overflow";
+
+/// Lock shards of the admitted attribute sets of one instrument.
+const SHARDS: usize = 16;
+
+/// Attribute rules the SDK views cannot express, applied to every
+/// measurement before it reaches the SDK.
+#[derive(Debug)]
+pub(crate) struct AttributePolicy {
+    views: Vec<MetricView>,
+    cardinality_limit: Option<usize>,
+    /// Shared by instruments created more than once under the same scope
+    instruments: Mutex<Instruments>,
+}
+
+impl AttributePolicy {
+    /// None when no view needs per-measurement work.
+    pub(crate) fn new(views: &[MetricView], cardinality_limit: Option<usize>) -> Option<Self> {
+        let needed = cardinality_limit.is_some()
+            || views
+                .iter()
+                .any(|view| !view.denied_attributes.is_empty() || view.cardinality_limit.is_some());
+        needed.then(|| Self {
+            views: views.to_vec(),
+            cardinality_limit,
+            instruments: Mutex::default(),
+        })
+    }
+
+    /// Meter provider applying the policy on top of `provider`.
+    pub(crate) fn wrap(self, provider: SdkMeterProvider) -> PolicyMeterProvider {
+        PolicyMeterProvider {
+            provider,
+            policy: Arc::new(self),
+        }
+    }
+
+    fn instrument(&self, scope: &str, name: &str) -> Option<Arc<InstrumentPolicy>> {
+        let mut instruments = self.instruments.lock().unwrap_or_else(|e| e.into_inner());
+        instruments
+            .entry((scope.to_owned(), name.to_owned()))
+            .or_insert_with(|| self.build(name))
+            .clone()
+    }
+
+    fn build(&self, name: &str) -> Option<Arc<InstrumentPolicy>> {
+        let view = self.views.iter().find(|view| view.matches(name));
+        let denied: Vec<Key> = view
+            .map(|view| {
+                view.denied_attributes
+                    .iter()
+                    .cloned()
+                    .map(Key::new)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let limit = view
+            .and_then(|view| view.cardinality_limit)
+            .or(self.cardinality_limit)
+            .map(|limit| CardinalityLimit::new(name, limit));
+        if denied.is_empty() && limit.is_none() {
+            return None;
+        }
+        let allowed = view
+            .and_then(|view| view.allowed_attributes.as_ref())
+            .map(|keys| keys.iter().cloned().map(Key::new).collect());
+        Some(Arc::new(InstrumentPolicy {
+            denied,
+            allowed,
+            limit,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct InstrumentPolicy {
+    denied: Vec<Key>,
+    /// The view's allow-list, also applied by the SDK, so the limiter counts
+    /// the attribute sets that are actually exported
+    allowed: Option<Vec<Key>>,
+    limit: Option<CardinalityLimit>,
+}
+
+impl InstrumentPolicy {
+    fn keeps(&self, key: &Key) -> bool {
+        !self.denied.contains(key)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(key))
+    }
+
+    /// Borrows `attributes` when nothing is removed, the common case.
+    fn apply<'a>(&'a self, attributes: &'a [KeyValue]) -> Cow<'a, [KeyValue]> {
+        let attributes = if attributes.iter().all(|kv| self.keeps(&kv.key)) {
+            Cow::Borrowed(attributes)
+        } else {
+            Cow::Owned(
+                attributes
+                    .iter()
+                    .filter(|kv| self.keeps(&kv.key))
+                    .cloned()
+                    .collect(),
+            )
+        };
+        match &self.limit {
+            Some(limit) if !limit.admit(&attributes) => Cow::Borrowed(&limit.overflow),
+            _ => attributes,
+        }
+    }
+}
+
+/// Caps the distinct attribute sets of one instrument since startup.
+///
+/// Sets are tracked by an order-independent fingerprint, so known sets cost
+/// one hash and a shared lock on one shard.
+#[derive(Debug)]
+struct CardinalityLimit {
+    instrument: String,
+    limit: usize,
+    shards: [RwLock<HashSet<u64>>; SHARDS],
+    admitted: AtomicUsize,
+    /// Value fingerprints per key of the admitted sets, dropped once the
+    /// warning names the key with the most values
+    values: Mutex<Option<HashMap<Key, HashSet<u64>>>>,
+    warned: AtomicBool,
+    overflow: [KeyValue; 1],
+}
+
+impl CardinalityLimit {
+    fn new(instrument: &str, limit: usize) -> Self {
+        Self {
+            instrument: instrument.to_owned(),
+            limit,
+            shards: Default::default(),
+            admitted: AtomicUsize::new(0),
+            values: Mutex::new(Some(HashMap::new())),
+            warned: AtomicBool::new(false),
+            overflow: [KeyValue::new(OVERFLOW, true)],
+        }
+    }
+
+    /// False when `attributes` is a new set past the limit.
+    fn admit(&self, attributes: &[KeyValue]) -> bool {
+        let fingerprint = attributes
+            .iter()
+            .map(|kv| hash(&kv.key, &kv.value))
+            .fold(0, u64::wrapping_add);
+        let shard = &self.shards[fingerprint as usize % SHARDS];
+        if shard
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&fingerprint)
+        {
+            return true;
+        }
+        if self.admitted.load(Ordering::Relaxed) < self.limit {
+            let mut admitted = shard.write().unwrap_or_else(|e| e.into_inner());
+            if admitted.contains(&fingerprint) {
+                return true;
+            }
+            if self.admitted.fetch_add(1, Ordering::Relaxed) < self.limit {
+                admitted.insert(fingerprint);
+                drop(admitted);
+                self.track(attributes);
+                return true;
+            }
+            self.admitted.fetch_sub(1, Ordering::Relaxed);
+        }
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            self.warn(attributes);
+        }
+        false
+    }
+
+    fn track(&self, attributes: &[KeyValue]) {
+        let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(values) = values.as_mut() {
+            for kv in attributes {
+                let value = hash(&kv.key, &kv.value);
+                values.entry(kv.key.clone()).or_default().insert(value);
+            }
+        }
+    }
+
+    /// Names the key with the most distinct values, the likely source of
+    /// unbounded cardinality.
+    fn warn(&self, attributes: &[KeyValue]) {
+        self.track(attributes);
+        let values = self
+            .values
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_default();
+        let attribute = values
+            .iter()
+            .max_by(|a, b| a.1.len().cmp(&b.1.len()).then_with(|| b.0.cmp(a.0)))
+            .map(|(key, _)| key.as_str())
+            .unwrap_or_default();
+        tracing::warn!(
+            instrument = %self.instrument,
+            limit = self.limit,
+            attribute,
+            "Metric cardinality limit reached, new attribute sets are recorded as {OVERFLOW}"
+        );
+    }
+}
+
+/// Hash of one attribute without allocating, summed into set fingerprints.
+fn hash(key: &Key, value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    match value {
+        Value::Bool(v) => (0u8, v).hash(&mut hasher),
+        Value::I64(v) => (1u8, v).hash(&mut hasher),
+        Value::F64(v) => (2u8, v.to_bits()).hash(&mut hasher),
+        Value::String(v) => (3u8, v.as_str()).hash(&mut hasher),
+        other => (4u8, other.as_str()).hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+/// Installed as the global meter provider when an `AttributePolicy` exists.
+#[derive(Debug, Clone)]
+pub(crate) struct PolicyMeterProvider {
+    provider: SdkMeterProvider,
+    policy: Arc<AttributePolicy>,
+}
+
+impl MeterProvider for PolicyMeterProvider {
+    fn meter_with_scope(&self, scope: InstrumentationScope) -> Meter {
+        Meter::new(Arc::new(PolicyInstruments {
+            scope: scope.name().to_owned(),
+            meter: self.provider.meter_with_scope(scope),
+            policy: self.policy.clone(),
+        }))
+    }
+}
+
+struct PolicyInstruments {
+    scope: String,
+    meter: Meter,
+    policy: Arc<AttributePolicy>,
+}
+
+/// Sync instrument recording into the SDK instrument after applying the policy.
+struct Filtered<I> {
+    inner: I,
+    policy: Arc<InstrumentPolicy>,
+}
+
+macro_rules! filtered {
+    ($instrument:ident, $record:ident) => {
+        impl<T> SyncInstrument<T> for Filtered<$instrument<T>>
+        where
+            $instrument<T>: Send + Sync,
+        {
+            fn measure(&self, value: T, attributes: &[KeyValue]) {
+                self.inner.$record(value, &self.policy.apply(attributes));
+            }
+        }
+    };
+}
+
+filtered!(Counter, add);
+filtered!(UpDownCounter, add);
+filtered!(Gauge, record);
+filtered!(Histogram, record);
+
+/// Observer handed to observable callbacks, applying the policy.
+struct FilteredObserver<'a, T> {
+    inner: &'a dyn AsyncInstrument<T>,
+    policy: &'a InstrumentPolicy,
+}
+
+impl<T> AsyncInstrument<T> for FilteredObserver<'_, T> {
+    fn observe(&self, value: T, attributes: &[KeyValue]) {
+        self.inner.observe(value, &self.policy.apply(attributes));
+    }
+}
+
+macro_rules! sync_instrument {
+    ($method:ident, $instrument:ident<$value:ty>) => {
+        fn $method(
+            &self,
+            builder: InstrumentBuilder<'_, $instrument<$value>>,
+        ) -> $instrument<$value> {
+            let mut inner = self.meter.$method(builder.name.clone());
+            if let Some(description) = builder.description {
+                inner = inner.with_description(description);
+            }
+            if let Some(unit) = builder.unit {
+                inner = inner.with_unit(unit);
+            }
+            let inner = inner.build();
+            match self.policy.instrument(&self.scope, &builder.name) {
+                Some(policy) => $instrument::new(Arc::new(Filtered { inner, policy })),
+                None => inner,
+            }
+        }
+    };
+}
+
+macro_rules! histogram {
+    ($method:ident, $value:ty) => {
+        fn $method(&self, builder: HistogramBuilder<'_, Histogram<$value>>) -> Histogram<$value> {
+            let mut inner = self.meter.$method(builder.name.clone());
+            if let Some(description) = builder.description {
+                inner = inner.with_description(description);
+            }
+            if let Some(unit) = builder.unit {
+                inner = inner.with_unit(unit);
+            }
+            if let Some(boundaries) = builder.boundaries {
+                inner = inner.with_boundaries(boundaries);
+            }
+            let inner = inner.build();
+            match self.policy.instrument(&self.scope, &builder.name) {
+                Some(policy) => Histogram::new(Arc::new(Filtered { inner, policy })),
+                None => inner,
+            }
+        }
+    };
+}
+
+macro_rules! observable_instrument {
+    ($method:ident, $instrument:ident<$value:ty>) => {
+        fn $method(
+            &self,
+            builder: AsyncInstrumentBuilder<'_, $instrument<$value>, $value>,
+        ) -> $instrument<$value> {
+            let mut inner = self.meter.$method(builder.name.clone());
+            if let Some(description) = builder.description {
+                inner = inner.with_description(description);
+            }
+            if let Some(unit) = builder.unit {
+                inner = inner.with_unit(unit);
+            }
+            let policy = self.policy.instrument(&self.scope, &builder.name);
+            for callback in builder.callbacks {
+                inner = match policy.clone() {
+                    Some(policy) => inner.with_callback(move |observer| {
+                        callback(&FilteredObserver {
+                            inner: observer,
+                            policy: &policy,
+                        })
+                    }),
+                    None => inner.with_callback(callback),
+                };
+            }
+            inner.build()
+        }
+    };
+}
+
+impl InstrumentProvider for PolicyInstruments {
+    sync_instrument!(u64_counter, Counter<u64>);
+    sync_instrument!(f64_counter, Counter<f64>);
+    sync_instrument!(i64_up_down_counter, UpDownCounter<i64>);
+    sync_instrument!(f64_up_down_counter, UpDownCounter<f64>);
+    sync_instrument!(u64_gauge, Gauge<u64>);
+    sync_instrument!(f64_gauge, Gauge<f64>);
+    sync_instrument!(i64_gauge, Gauge<i64>);
+    histogram!(u64_histogram, u64);
+    histogram!(f64_histogram, f64);
+    observable_instrument!(u64_observable_counter, ObservableCounter<u64>);
+    observable_instrument!(f64_observable_counter, ObservableCounter<f64>);
+    observable_instrument!(i64_observable_up_down_counter, ObservableUpDownCounter<i64>);
+    observable_instrument!(f64_observable_up_down_counter, ObservableUpDownCounter<f64>);
+    observable_instrument!(u64_observable_gauge, ObservableGauge<u64>);
+    observable_instrument!(i64_observable_gauge, ObservableGauge<i64>);
+    observable_instrument!(f64_observable_gauge, ObservableGauge<f64>);
+}
+
+#[cfg(test)]
+mod test {
+    use super::AttributePolicy;
+    use crate::MetricView;
+    use opentelemetry::KeyValue;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    #[test]
+    pub fn observable_callbacks_are_filtered() {
+        let metrics = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let views = [MetricView::new("queue.*").deny_attributes(["queue.id"])];
+        let meter = AttributePolicy::new(&views, None)
+            .unwrap()
+            .wrap(provider.clone())
+            .meter("test");
+        let _gauge = meter
+            .u64_observable_gauge("queue.depth")
+            .with_callback(|observer| {
+                observer.observe(
+                    3,
+                    &[
+                        KeyValue::new("queue.id", "7f3a"),
+                        KeyValue::new("queue.kind", "blocks"),
+                    ],
+                )
+            })
+            .build();
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let metric = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == "queue.depth")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Gauge(gauge)) = metric.data() else {
+            panic!("queue.depth is not a gauge");
+        };
+        let point = gauge.data_points().next().unwrap();
+        assert_eq!(point.value(), 3);
+        assert_eq!(
+            point.attributes().collect::<Vec<_>>(),
+            vec![&KeyValue::new("queue.kind", "blocks")]
+        );
+    }
+
+    #[test]
+    pub fn cardinality_overflow_is_folded_and_reported_once() {
+        use crate::test::BufferWriter;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let metrics = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let views = [MetricView::new("logins").cardinality_limit(3)];
+        let meter = AttributePolicy::new(&views, None)
+            .unwrap()
+            .wrap(provider.clone())
+            .meter("test");
+        let counter = meter.u64_counter("logins").build();
+
+        let buffer = BufferWriter::default();
+        let writer = buffer.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .without_time()
+            .with_ansi(false)
+            .with_writer(move || writer.clone());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            for user in ["u1", "u2", "u3", "u4", "u5", "u1"] {
+                counter.add(
+                    1,
+                    &[
+                        KeyValue::new("user.id", user),
+                        KeyValue::new("region", "eu"),
+                    ],
+                );
+            }
+        });
+        provider.force_flush().unwrap();
+
+        let output = buffer.contents();
+        assert_eq!(output.matches("cardinality limit reached").count(), 1);
+        assert!(output.contains("instrument=logins"));
+        assert!(output.contains("attribute=\"user.id\""));
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let metric = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == "logins")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = metric.data() else {
+            panic!("logins is not a sum");
+        };
+        let overflow = KeyValue::new("otel.metric.overflow", true);
+        let mut series = 0;
+        for point in sum.data_points() {
+            let attributes: Vec<_> = point.attributes().collect();
+            if attributes == vec![&overflow] {
+                assert_eq!(point.value(), 2);
+            } else {
+                series += 1;
+            }
+        }
+        assert_eq!(series, 3);
+        assert_eq!(crate::test::metric_sum(&metrics, "logins"), 6);
+    }
+
+    #[test]
+    pub fn allowed_attributes_are_projected_before_counting() {
+        let metrics = InMemoryMetricExporter::default();
+        let views = [MetricView::new("logins")
+            .allow_attributes(["region"])
+            .cardinality_limit(1)];
+        let builder = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build());
+        let provider = crate::views::register(builder, &views, None, None)
+            .unwrap()
+            .build();
+        let meter = AttributePolicy::new(&views, None)
+            .unwrap()
+            .wrap(provider.clone())
+            .meter("test");
+        let counter = meter.u64_counter("logins").build();
+        for (user, region) in [("u1", "eu"), ("u2", "eu"), ("u3", "us")] {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("user.id", user),
+                    KeyValue::new("region", region),
+                ],
+            );
+        }
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let metric = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == "logins")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = metric.data() else {
+            panic!("logins is not a sum");
+        };
+        // user.id never counts, only the second region overflows.
+        let mut points: Vec<_> = sum
+            .data_points()
+            .map(|point| {
+                (
+                    point.attributes().cloned().collect::<Vec<_>>(),
+                    point.value(),
+                )
+            })
+            .collect();
+        points.sort_by_key(|(_, value)| *value);
+        assert_eq!(
+            points,
+            vec![
+                (vec![KeyValue::new("otel.metric.overflow", true)], 1),
+                (vec![KeyValue::new("region", "eu")], 2),
+            ]
+        );
+    }
+}
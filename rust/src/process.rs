@@ -0,0 +1,214 @@
+use std::time::Instant;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+
+/// Kernel clock ticks per second used by `/proc/<pid>/stat` (`USER_HZ`).
+/// Assumed rather than read from `sysconf(_SC_CLK_TCK)`: it is 100 on x86,
+/// ARM, RISC-V and the other common architectures, but 1024 on Alpha and
+/// IA-64, where CPU time would be about ten times too high and uptime wrong.
+const TICKS_PER_SECOND: f64 = 100.0;
+
+/// Registers the process observable instruments on `meter`. Values are read
+/// from `/proc/self` on every collection and skipped where it is missing,
+/// except `process.uptime` which falls back to the time since registration.
+pub(crate) fn register(meter: &Meter) {
+    meter
+        .f64_observable_counter("process.cpu.time")
+        .with_description("Total CPU seconds broken down by mode")
+        .with_unit("s")
+        .with_callback(|observer| {
+            if let Some(stat) = read_stat() {
+                observer.observe(stat.user_seconds, &[KeyValue::new("cpu.mode", "user")]);
+                observer.observe(stat.system_seconds, &[KeyValue::new("cpu.mode", "system")]);
+            }
+        })
+        .build();
+    meter
+        .i64_observable_up_down_counter("process.memory.usage")
+        .with_description("Resident set size of the process")
+        .with_unit("By")
+        .with_callback(|observer| {
+            if let Some(status) = read_status() {
+                observer.observe(status.resident_bytes, &[]);
+            }
+        })
+        .build();
+    meter
+        .i64_observable_up_down_counter("process.memory.virtual")
+        .with_description("Virtual memory size of the process")
+        .with_unit("By")
+        .with_callback(|observer| {
+            if let Some(status) = read_status() {
+                observer.observe(status.virtual_bytes, &[]);
+            }
+        })
+        .build();
+    meter
+        .i64_observable_up_down_counter("process.unix.file_descriptor.count")
+        .with_description("Number of open file descriptors")
+        .with_unit("{file_descriptor}")
+        .with_callback(|observer| {
+            if let Some(count) = open_file_descriptors() {
+                observer.observe(count, &[]);
+            }
+        })
+        .build();
+    meter
+        .i64_observable_up_down_counter("process.thread.count")
+        .with_description("Number of threads of the process")
+        .with_unit("{thread}")
+        .with_callback(|observer| {
+            if let Some(status) = read_status() {
+                observer.observe(status.threads, &[]);
+            }
+        })
+        .build();
+    let registered = Instant::now();
+    meter
+        .f64_observable_gauge("process.uptime")
+        .with_description("Seconds since the process started")
+        .with_unit("s")
+        .with_callback(move |observer| {
+            let uptime = read_stat()
+                .zip(system_uptime())
+                .map(|(stat, system)| system - stat.start_seconds)
+                .unwrap_or_else(|| registered.elapsed().as_secs_f64());
+            observer.observe(uptime, &[]);
+        })
+        .build();
+}
+
+#[derive(Debug, PartialEq)]
+struct Stat {
+    user_seconds: f64,
+    system_seconds: f64,
+    /// Seconds after system boot
+    start_seconds: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Status {
+    resident_bytes: i64,
+    virtual_bytes: i64,
+    threads: i64,
+}
+
+fn read_stat() -> Option<Stat> {
+    parse_stat(&std::fs::read_to_string("/proc/self/stat").ok()?)
+}
+
+fn read_status() -> Option<Status> {
+    parse_status(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn system_uptime() -> Option<f64> {
+    let text = std::fs::read_to_string("/proc/uptime").ok()?;
+    text.split_whitespace().next()?.parse().ok()
+}
+
+fn open_file_descriptors() -> Option<i64> {
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?.count() as i64;
+    // The directory handle being read is listed too.
+    Some(entries.saturating_sub(1))
+}
+
+/// Parses `/proc/<pid>/stat`. The command name may contain spaces and
+/// parentheses, so fields are counted after its last `)`.
+fn parse_stat(text: &str) -> Option<Stat> {
+    let (_, rest) = text.rsplit_once(')')?;
+    // Fields after the name start at 3 (state), see proc_pid_stat(5).
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |number: usize| -> Option<f64> { fields.get(number - 3)?.parse().ok() };
+    Some(Stat {
+        user_seconds: field(14)? / TICKS_PER_SECOND,
+        system_seconds: field(15)? / TICKS_PER_SECOND,
+        start_seconds: field(22)? / TICKS_PER_SECOND,
+    })
+}
+
+/// Parses `VmRSS`, `VmSize` (in kB) and `Threads` from `/proc/<pid>/status`.
+fn parse_status(text: &str) -> Option<Status> {
+    let value = |name: &str| -> Option<i64> {
+        let line = text.lines().find_map(|line| line.strip_prefix(name))?;
+        line.trim_start_matches(':')
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    Some(Status {
+        resident_bytes: value("VmRSS")? * 1024,
+        virtual_bytes: value("VmSize")? * 1024,
+        threads: value("Threads")?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Stat, Status, parse_stat, parse_status};
+
+    #[test]
+    pub fn proc_files_are_parsed() {
+        let stat = "2375 (my (odd) app) S 2371 2375 2371 0 -1 4194304 81 0 0 0 \
+                    250 125 0 0 20 0 4 0 306873 2703360 272 18446744073709551615";
+        assert_eq!(
+            parse_stat(stat),
+            Some(Stat {
+                user_seconds: 2.5,
+                system_seconds: 1.25,
+                start_seconds: 3068.73,
+            })
+        );
+        assert_eq!(parse_stat("2375 (cat) R 2371"), None);
+
+        let status = "Name:\tapp\nVmSize:\t    3340 kB\nVmRSS:\t    1664 kB\nThreads:\t4\n";
+        assert_eq!(
+            parse_status(status),
+            Some(Status {
+                resident_bytes: 1664 * 1024,
+                virtual_bytes: 3340 * 1024,
+                threads: 4,
+            })
+        );
+        // Kernel threads have no memory lines.
+        assert_eq!(parse_status("Name:\tkthreadd\nThreads:\t1\n"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    pub fn process_instruments_are_collected() {
+        use opentelemetry::metrics::MeterProvider;
+        use opentelemetry_sdk::metrics::{
+            InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+        };
+
+        let metrics = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        crate::MetricsHelper::process_metrics(&provider.meter("test"));
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let mut names: Vec<_> = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .map(|metric| metric.name().to_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "process.cpu.time",
+                "process.memory.usage",
+                "process.memory.virtual",
+                "process.thread.count",
+                "process.unix.file_descriptor.count",
+                "process.uptime",
+            ]
+        );
+    }
+}
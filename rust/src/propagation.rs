@@ -0,0 +1,322 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use opentelemetry::propagation::{TextMapCompositePropagator, TextMapPropagator};
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+
+/// Context propagation format for `OtelParams::propagators`.
+///
+/// `B3`/`B3Multi` and `Jaeger` require the `otel-b3` and `otel-jaeger` features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropagationFormat {
+    /// W3C `traceparent` / `tracestate`
+    TraceContext,
+    /// W3C `baggage`
+    Baggage,
+    /// Single `b3` header
+    #[cfg(feature = "otel-b3")]
+    B3,
+    /// `X-B3-TraceId`, `X-B3-SpanId`, ... headers
+    #[cfg(feature = "otel-b3")]
+    B3Multi,
+    /// `uber-trace-id` and `uberctx-*` headers
+    #[cfg(feature = "otel-jaeger")]
+    Jaeger,
+}
+
+impl PropagationFormat {
+    /// Parses the comma separated `OTEL_PROPAGATORS` value, unknown or
+    /// unsupported entries are skipped with a warning and `none` disables
+    /// propagation.
+    pub fn from_env() -> Option<Vec<Self>> {
+        let value = std::env::var("OTEL_PROPAGATORS").ok()?;
+        let (formats, skipped) = Self::parse_list(&value);
+        Self::warn_skipped(&skipped);
+        Some(formats)
+    }
+
+    /// Formats named in `value` and the entries that were skipped.
+    pub(crate) fn parse_list(value: &str) -> (Vec<Self>, Vec<String>) {
+        let mut formats = Vec::new();
+        let mut skipped = Vec::new();
+        for name in value.split(',').map(str::trim) {
+            match name.parse() {
+                Ok(format) => formats.push(format),
+                Err(_) if name.is_empty() || name == "none" => {}
+                Err(_) => skipped.push(name.to_owned()),
+            }
+        }
+        (formats, skipped)
+    }
+
+    pub(crate) fn warn_skipped(skipped: &[String]) {
+        for name in skipped {
+            tracing::warn!(
+                propagator = %name,
+                "Skipping unknown or disabled OTEL_PROPAGATORS entry"
+            );
+        }
+    }
+
+    /// Code configuration first, then the environment, then tracecontext.
+    pub(crate) fn resolve(configured: Option<&[Self]>) -> Vec<Self> {
+        configured
+            .map(<[Self]>::to_vec)
+            .or_else(Self::from_env)
+            .unwrap_or_else(|| vec![Self::TraceContext])
+    }
+
+    /// Composite propagator, injecting every format and extracting them in order.
+    pub(crate) fn build(formats: &[Self]) -> TextMapCompositePropagator {
+        let propagators = formats
+            .iter()
+            .map(|format| -> Box<dyn TextMapPropagator + Send + Sync> {
+                match format {
+                    Self::TraceContext => Box::new(TraceContextPropagator::new()),
+                    Self::Baggage => Box::new(BaggagePropagator::new()),
+                    #[cfg(feature = "otel-b3")]
+                    Self::B3 => Box::new(opentelemetry_zipkin::Propagator::with_encoding(
+                        opentelemetry_zipkin::B3Encoding::SingleHeader,
+                    )),
+                    #[cfg(feature = "otel-b3")]
+                    Self::B3Multi => Box::new(opentelemetry_zipkin::Propagator::with_encoding(
+                        opentelemetry_zipkin::B3Encoding::MultipleHeader,
+                    )),
+                    #[cfg(feature = "otel-jaeger")]
+                    Self::Jaeger => Box::new(opentelemetry_jaeger_propagator::Propagator::new()),
+                }
+            })
+            .collect();
+        TextMapCompositePropagator::new(propagators)
+    }
+}
+
+/// Remote parent carried by incoming request headers, read with the global
+/// propagator configured in `build_otel_layers`.
+#[cfg(feature = "otel-http")]
+pub fn extract_context(headers: &http::HeaderMap) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&opentelemetry_http::HeaderExtractor(headers))
+    })
+}
+
+/// Writes the context of the current tracing span into outgoing request headers.
+#[cfg(feature = "otel-http")]
+pub fn inject_context(headers: &mut http::HeaderMap) {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let cx = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut opentelemetry_http::HeaderInjector(headers))
+    });
+}
+
+/// Makes the remote context in `headers` the parent of the request span.
+///
+/// Call it right after creating `span`, it fails once the span has been
+/// entered since that starts its OpenTelemetry span.
+#[cfg(feature = "otel-http")]
+pub fn set_parent_from_headers(
+    span: &tracing::Span,
+    headers: &http::HeaderMap,
+) -> Result<(), tracing_opentelemetry::SetParentError> {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    span.set_parent(extract_context(headers))
+}
+
+impl FromStr for PropagationFormat {
+    type Err = String;
+
+    /// Accepts the `OTEL_PROPAGATORS` names.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "tracecontext" => Ok(Self::TraceContext),
+            "baggage" => Ok(Self::Baggage),
+            #[cfg(feature = "otel-b3")]
+            "b3" => Ok(Self::B3),
+            #[cfg(feature = "otel-b3")]
+            "b3multi" => Ok(Self::B3Multi),
+            #[cfg(feature = "otel-jaeger")]
+            "jaeger" => Ok(Self::Jaeger),
+            other => Err(format!("unsupported propagator: {other}")),
+        }
+    }
+}
+
+impl Display for PropagationFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::TraceContext => "tracecontext",
+            Self::Baggage => "baggage",
+            #[cfg(feature = "otel-b3")]
+            Self::B3 => "b3",
+            #[cfg(feature = "otel-b3")]
+            Self::B3Multi => "b3multi",
+            #[cfg(feature = "otel-jaeger")]
+            Self::Jaeger => "jaeger",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PropagationFormat;
+    use opentelemetry::baggage::BaggageExt;
+    use opentelemetry::propagation::TextMapPropagator;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::{Context, KeyValue};
+    use std::collections::HashMap;
+
+    fn remote_context() -> Context {
+        let span_context = SpanContext::new(
+            TraceId::from(0x4bf92f3577b34da6a3ce929d0e0e4736),
+            SpanId::from(0x00f067aa0ba902b7),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        Context::new()
+            .with_remote_span_context(span_context)
+            .with_baggage([KeyValue::new("tenant", "acme")])
+    }
+
+    #[test]
+    pub fn composite_injects_and_extracts() {
+        let propagator = PropagationFormat::build(&[
+            PropagationFormat::TraceContext,
+            PropagationFormat::Baggage,
+        ]);
+        let mut headers = HashMap::new();
+        propagator.inject_context(&remote_context(), &mut headers);
+
+        assert_eq!(
+            headers["traceparent"],
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(headers["baggage"], "tenant=acme");
+
+        let extracted = propagator.extract(&headers);
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            TraceId::from(0x4bf92f3577b34da6a3ce929d0e0e4736)
+        );
+        assert_eq!(
+            extracted.baggage().get("tenant").map(|v| v.to_string()),
+            Some("acme".to_owned())
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "otel-b3", feature = "otel-jaeger"))]
+    pub fn b3_and_jaeger_are_supported() {
+        let (formats, _) = PropagationFormat::parse_list("tracecontext, b3multi, jaeger");
+        let propagator = PropagationFormat::build(&formats);
+        let mut headers = HashMap::new();
+        propagator.inject_context(&remote_context(), &mut headers);
+
+        assert_eq!(headers["x-b3-traceid"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(
+            headers["uber-trace-id"],
+            "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1"
+        );
+
+        // An upstream sending only B3 still continues the trace.
+        let b3_only: HashMap<String, String> = [(
+            "b3".to_owned(),
+            "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1".to_owned(),
+        )]
+        .into();
+        let propagator =
+            PropagationFormat::build(&[PropagationFormat::TraceContext, PropagationFormat::B3]);
+        let extracted = propagator.extract(&b3_only);
+        assert!(extracted.span().span_context().is_sampled());
+        assert_eq!(
+            extracted.span().span_context().span_id(),
+            SpanId::from(0x00f067aa0ba902b7)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "otel-http")]
+    pub fn header_map_helpers_use_global_propagator() {
+        use super::{inject_context, set_parent_from_headers};
+        use opentelemetry::trace::TracerProvider;
+        use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        opentelemetry::global::set_text_map_propagator(PropagationFormat::build(&[
+            PropagationFormat::TraceContext,
+        ]));
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        let mut incoming = http::HeaderMap::new();
+        incoming.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+        let mut outgoing = http::HeaderMap::new();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("handle_request");
+            set_parent_from_headers(&span, &incoming).unwrap();
+            span.in_scope(|| inject_context(&mut outgoing));
+        });
+
+        let traceparent = outgoing["traceparent"].to_str().unwrap();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans[0].parent_span_id, SpanId::from(0x00f067aa0ba902b7));
+        assert!(spans[0].parent_span_is_remote);
+    }
+
+    #[test]
+    pub fn env_values_are_parsed() {
+        assert_eq!(
+            PropagationFormat::parse_list("tracecontext,baggage,xray"),
+            (
+                vec![PropagationFormat::TraceContext, PropagationFormat::Baggage],
+                vec!["xray".to_owned()]
+            )
+        );
+        assert_eq!(PropagationFormat::parse_list("none"), (vec![], vec![]));
+        assert_eq!(
+            PropagationFormat::parse_list("baggage,"),
+            (vec![PropagationFormat::Baggage], vec![])
+        );
+    }
+
+    #[test]
+    pub fn skipped_entries_are_warned() {
+        use crate::test::BufferWriter;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = BufferWriter::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let (_, skipped) = PropagationFormat::parse_list("tracecontext,xray,ottrace");
+            PropagationFormat::warn_skipped(&skipped);
+        });
+
+        let output = buffer.contents();
+        assert_eq!(output.matches("WARN").count(), 2);
+        assert!(output.contains("propagator=xray"));
+        assert!(output.contains("propagator=ottrace"));
+    }
+}
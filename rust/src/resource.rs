@@ -0,0 +1,232 @@
+use opentelemetry::KeyValue;
+
+/// Opt-in resource detectors for `OtelParams::resource_detectors`.
+///
+/// Detected attributes are overridden by `OTEL_RESOURCE_ATTRIBUTES` and by
+/// `OtelParams::resource_attributes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceDetectors {
+    /// `host.name`, `host.arch`
+    pub host: bool,
+    /// `os.type`
+    pub os: bool,
+    /// `process.pid`, `process.executable.name`/`path`, `process.runtime.name`
+    pub process: bool,
+    /// `container.id` from `/proc/self/cgroup` or `/proc/self/mountinfo`
+    pub container: bool,
+    /// `k8s.pod.name`, `k8s.pod.uid`, `k8s.namespace.name` and `k8s.node.name`
+    /// from `K8S_POD_NAME`, `K8S_POD_UID`, `K8S_NAMESPACE_NAME` and
+    /// `K8S_NODE_NAME`, set through the downward API
+    pub kubernetes: bool,
+    /// `deployment.environment` from `DEPLOYMENT_ENVIRONMENT`
+    pub deployment: bool,
+}
+
+impl ResourceDetectors {
+    pub fn all() -> Self {
+        Self {
+            host: true,
+            os: true,
+            process: true,
+            container: true,
+            kubernetes: true,
+            deployment: true,
+        }
+    }
+
+    /// Runs the enabled detectors, missing values are skipped.
+    pub fn detect(&self) -> Vec<KeyValue> {
+        let mut attributes = Vec::new();
+        if self.host {
+            if let Some(name) = hostname() {
+                attributes.push(KeyValue::new("host.name", name));
+            }
+            attributes.push(KeyValue::new("host.arch", host_arch()));
+        }
+        if self.os {
+            attributes.push(KeyValue::new("os.type", os_type()));
+        }
+        if self.process {
+            attributes.push(KeyValue::new("process.pid", std::process::id() as i64));
+            if let Ok(path) = std::env::current_exe() {
+                if let Some(name) = path.file_name() {
+                    attributes.push(KeyValue::new(
+                        "process.executable.name",
+                        name.to_string_lossy().into_owned(),
+                    ));
+                }
+                attributes.push(KeyValue::new(
+                    "process.executable.path",
+                    path.to_string_lossy().into_owned(),
+                ));
+            }
+            attributes.push(KeyValue::new("process.runtime.name", "rust"));
+        }
+        if self.container {
+            let read = |path| std::fs::read_to_string(path).ok();
+            let id = read("/proc/self/cgroup")
+                .and_then(|text| container_id(&text))
+                .or_else(|| {
+                    read("/proc/self/mountinfo").and_then(|text| mountinfo_container_id(&text))
+                });
+            if let Some(id) = id {
+                attributes.push(KeyValue::new("container.id", id));
+            }
+        }
+        if self.kubernetes {
+            for (key, var) in [
+                ("k8s.pod.name", "K8S_POD_NAME"),
+                ("k8s.pod.uid", "K8S_POD_UID"),
+                ("k8s.namespace.name", "K8S_NAMESPACE_NAME"),
+                ("k8s.node.name", "K8S_NODE_NAME"),
+            ] {
+                if let Some(value) = env_value(var) {
+                    attributes.push(KeyValue::new(key, value));
+                }
+            }
+        }
+        if self.deployment
+            && let Some(environment) = env_value("DEPLOYMENT_ENVIRONMENT")
+        {
+            // `deployment.environment.name` is the current semconv key, many
+            // backends still group by the older one.
+            attributes.push(KeyValue::new("deployment.environment", environment.clone()));
+            attributes.push(KeyValue::new("deployment.environment.name", environment));
+        }
+        attributes
+    }
+}
+
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_owned())
+        .ok()
+        .or_else(|| env_value("HOSTNAME"))
+        .filter(|h| !h.is_empty())
+}
+
+/// Maps Rust target names to semconv `host.arch` values.
+fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        "arm" => "arm32",
+        "powerpc64" => "ppc64",
+        "s390x" => "s390x",
+        other => other,
+    }
+}
+
+/// Maps Rust target names to semconv `os.type` values.
+fn os_type() -> &'static str {
+    match std::env::consts::OS {
+        "macos" | "ios" => "darwin",
+        other => other,
+    }
+}
+
+/// Finds a 64 hex digit container id in cgroup paths, e.g.
+/// `0::/system.slice/docker-<id>.scope`.
+fn container_id(text: &str) -> Option<String> {
+    text.lines()
+        .flat_map(|line| line.split(['/', ' ']))
+        .flat_map(|segment| segment.split(['-', ':', '.']))
+        .find(|token| is_container_id(token))
+        .map(str::to_owned)
+}
+
+/// Finds the container id in mount sources like
+/// `/var/lib/docker/containers/<id>/hostname`, the fallback under cgroup v2.
+/// Other 64 hex digit tokens, such as overlay2 layer ids, are not container ids.
+fn mountinfo_container_id(text: &str) -> Option<String> {
+    text.lines()
+        .flat_map(|line| line.split(' '))
+        .find_map(|path| {
+            let segments: Vec<&str> = path.split('/').collect();
+            segments
+                .windows(2)
+                .find(|pair| pair[0] == "containers" && is_container_id(pair[1]))
+                .map(|pair| pair[1].to_owned())
+        })
+}
+
+fn is_container_id(token: &str) -> bool {
+    token.len() == 64 && token.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ResourceDetectors, container_id, mountinfo_container_id};
+
+    #[test]
+    pub fn container_id_is_parsed() {
+        let id = "b1f3c8a6e4d2f0a9c7e5b3d1f9a7c5e3b1d9f7a5c3e1b9d7f5a3c1e9b7d5f3a1";
+        assert_eq!(
+            container_id(&format!("0::/system.slice/docker-{id}.scope\n")).as_deref(),
+            Some(id)
+        );
+        assert_eq!(
+            container_id(&format!(
+                "12:memory:/kubepods/burstable/pod1234/cri-containerd-{id}\n"
+            ))
+            .as_deref(),
+            Some(id)
+        );
+        assert_eq!(container_id("0::/user.slice/user-1000.slice\n"), None);
+    }
+
+    #[test]
+    pub fn container_id_is_not_an_overlay_layer() {
+        let id = "b1f3c8a6e4d2f0a9c7e5b3d1f9a7c5e3b1d9f7a5c3e1b9d7f5a3c1e9b7d5f3a1";
+        let layer = "4f2d7e1c9b3a5d8f6e0c2b4a7d9f1e3c5b8a0d2f4e6c8b1a3d5f7e9c0b2a4d6f";
+        // Docker with overlay2 under cgroup v2, the root mount lists the
+        // layer ids before any bind mount.
+        let mountinfo = format!(
+            "672 589 0:53 / / rw,relatime master:313 - overlay overlay rw,\
+             lowerdir=/var/lib/docker/overlay2/l/QKZ5RQ3BNCLTRMZHFQVAVJXHUX:\
+             /var/lib/docker/overlay2/l/6JX3JOYHQQC2TYBYJHDQ5LDVOE,\
+             upperdir=/var/lib/docker/overlay2/{layer}/diff,\
+             workdir=/var/lib/docker/overlay2/{layer}/work,nouserxattr\n\
+             673 672 0:56 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw\n\
+             689 672 254:1 /docker/containers/{id}/resolv.conf /etc/resolv.conf rw,relatime - ext4 /dev/vda1 rw\n\
+             690 672 254:1 /docker/containers/{id}/hostname /etc/hostname rw,relatime - ext4 /dev/vda1 rw\n\
+             691 672 254:1 /docker/containers/{id}/hosts /etc/hosts rw,relatime - ext4 /dev/vda1 rw\n"
+        );
+        assert_eq!(mountinfo_container_id(&mountinfo).as_deref(), Some(id));
+        assert_eq!(
+            mountinfo_container_id(&format!(
+                "912 903 0:124 /var/lib/docker/containers/{id}/hostname /etc/hostname rw\n"
+            ))
+            .as_deref(),
+            Some(id)
+        );
+        let without_bind_mounts = mountinfo.lines().next().unwrap();
+        assert_eq!(mountinfo_container_id(without_bind_mounts), None);
+    }
+
+    #[test]
+    pub fn only_enabled_detectors_run() {
+        let keys = |detectors: ResourceDetectors| -> Vec<String> {
+            detectors
+                .detect()
+                .into_iter()
+                .map(|kv| kv.key.to_string())
+                .collect()
+        };
+
+        assert!(keys(ResourceDetectors::default()).is_empty());
+        let process = keys(ResourceDetectors {
+            process: true,
+            os: true,
+            ..Default::default()
+        });
+        assert!(process.contains(&"process.pid".to_owned()));
+        assert!(process.contains(&"os.type".to_owned()));
+        assert!(!process.iter().any(|k| k.starts_with("host.")));
+    }
+}
@@ -0,0 +1,121 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use tokio::runtime::Handle;
+
+/// Registers observable instruments reading `handle.metrics()` on every
+/// collection. `tokio.budget_forced_yields` needs `--cfg tokio_unstable`.
+pub(crate) fn register(meter: &Meter, handle: Handle) {
+    let runtime = handle.clone();
+    meter
+        .u64_observable_gauge("tokio.workers")
+        .with_description("Worker threads of the runtime")
+        .with_unit("{worker}")
+        .with_callback(move |observer| {
+            observer.observe(runtime.metrics().num_workers() as u64, &[])
+        })
+        .build();
+    let runtime = handle.clone();
+    meter
+        .u64_observable_gauge("tokio.tasks.alive")
+        .with_description("Tasks spawned on the runtime that have not completed")
+        .with_unit("{task}")
+        .with_callback(move |observer| {
+            observer.observe(runtime.metrics().num_alive_tasks() as u64, &[])
+        })
+        .build();
+    let runtime = handle.clone();
+    meter
+        .u64_observable_gauge("tokio.global_queue.depth")
+        .with_description("Tasks waiting in the runtime's global queue")
+        .with_unit("{task}")
+        .with_callback(move |observer| {
+            observer.observe(runtime.metrics().global_queue_depth() as u64, &[])
+        })
+        .build();
+    #[cfg(tokio_unstable)]
+    {
+        let runtime = handle.clone();
+        meter
+            .u64_observable_counter("tokio.budget_forced_yields")
+            .with_description("Times a task was forced to yield after exhausting its budget")
+            .with_unit("{yield}")
+            .with_callback(move |observer| {
+                observer.observe(runtime.metrics().budget_forced_yield_count(), &[])
+            })
+            .build();
+    }
+    meter
+        .f64_observable_counter("tokio.worker.busy.duration")
+        .with_description("Time each worker spent executing tasks")
+        .with_unit("s")
+        .with_callback(move |observer| {
+            let metrics = handle.metrics();
+            for worker in 0..metrics.num_workers() {
+                observer.observe(
+                    metrics.worker_total_busy_duration(worker).as_secs_f64(),
+                    &[KeyValue::new("tokio.worker", worker as i64)],
+                );
+            }
+        })
+        .build();
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    #[test]
+    pub fn runtime_instruments_are_collected() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+        let metrics = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        crate::MetricsHelper::tokio_runtime_metrics(&provider.meter("test"), runtime.handle());
+        let (_release, wait) = tokio::sync::oneshot::channel::<()>();
+        let (started, running) = std::sync::mpsc::channel();
+        runtime.spawn(async move {
+            _ = started.send(());
+            _ = wait.await;
+        });
+        // Spawned from outside the runtime the task sits in the global queue
+        // until a worker picks it up.
+        running.recv().unwrap();
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let gauge = |name: &str| {
+            let metric = snapshot
+                .last()
+                .unwrap()
+                .scope_metrics()
+                .flat_map(|scope| scope.metrics())
+                .find(|metric| metric.name() == name)
+                .unwrap();
+            let AggregatedMetrics::U64(MetricData::Gauge(gauge)) = metric.data() else {
+                panic!("{name} is not a u64 gauge");
+            };
+            gauge.data_points().next().unwrap().value()
+        };
+        assert_eq!(gauge("tokio.workers"), 2);
+        assert_eq!(gauge("tokio.tasks.alive"), 1);
+        assert_eq!(gauge("tokio.global_queue.depth"), 0);
+
+        let busy = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == "tokio.worker.busy.duration")
+            .unwrap();
+        let AggregatedMetrics::F64(MetricData::Sum(sum)) = busy.data() else {
+            panic!("tokio.worker.busy.duration is not a sum");
+        };
+        assert_eq!(sum.data_points().count(), 2);
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt::{Display, Formatter};
+
+use opentelemetry::trace::{Link, SamplingResult, SpanKind, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::trace::{Sampler, ShouldSample};
+
+/// Trace sampler configuration for `OtelParams::sampler`.
+///
+/// When unset, `OTEL_TRACES_SAMPLER` / `OTEL_TRACES_SAMPLER_ARG` are used and
+/// the SDK default `ParentBased(AlwaysOn)` otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SamplerConfig {
+    AlwaysOn,
+    AlwaysOff,
+    /// Ratio in `0.0..=1.0`
+    TraceIdRatio(f64),
+    /// Follows the parent decision, uses the inner sampler for root spans
+    ParentBased(Box<SamplerConfig>),
+    /// First matching rule decides, `fallback` otherwise
+    Rules {
+        rules: Vec<SamplingRule>,
+        fallback: Box<SamplerConfig>,
+    },
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self::ParentBased(Box::new(Self::AlwaysOn))
+    }
+}
+
+/// Matches a span when every set condition holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingRule {
+    /// Exact span name
+    pub span_name: Option<String>,
+    /// Target prefix, e.g. `my_service::db` also matches `my_service::db::pool`
+    pub target: Option<String>,
+    /// Attributes that must be present with exactly these values
+    pub attributes: Vec<(String, String)>,
+    pub sampler: SamplerConfig,
+}
+
+impl SamplingRule {
+    pub fn new(sampler: SamplerConfig) -> Self {
+        Self {
+            span_name: None,
+            target: None,
+            attributes: Vec::new(),
+            sampler,
+        }
+    }
+
+    pub fn span_name(mut self, value: impl Into<String>) -> Self {
+        self.span_name = Some(value.into());
+        self
+    }
+
+    pub fn target(mut self, value: impl Into<String>) -> Self {
+        self.target = Some(value.into());
+        self
+    }
+
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.push((key.into(), value.into()));
+        self
+    }
+
+    fn matches(&self, name: &str, attributes: &[KeyValue]) -> bool {
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.as_str())
+        };
+
+        if self.span_name.as_ref().is_some_and(|n| n != name) {
+            return false;
+        }
+        if let Some(target) = &self.target {
+            // tracing-opentelemetry records the tracing target as `target`
+            let Some(span_target) = attribute("target") else {
+                return false;
+            };
+            let nested = span_target
+                .strip_prefix(target.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"));
+            if !nested {
+                return false;
+            }
+        }
+        self.attributes
+            .iter()
+            .all(|(key, value)| attribute(key).is_some_and(|v| v == value.as_str()))
+    }
+}
+
+impl SamplerConfig {
+    /// Parses `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG`.
+    pub fn from_env() -> Option<Self> {
+        let name = std::env::var("OTEL_TRACES_SAMPLER").ok()?;
+        let arg = std::env::var("OTEL_TRACES_SAMPLER_ARG").ok();
+        Self::from_spec(&name, arg.as_deref())
+    }
+
+    /// Parses the spec sampler names, an invalid ratio argument means 1.0.
+    pub fn from_spec(name: &str, arg: Option<&str>) -> Option<Self> {
+        let ratio = || {
+            arg.and_then(|a| a.trim().parse::<f64>().ok())
+                .filter(|r| (0.0..=1.0).contains(r))
+                .unwrap_or(1.0)
+        };
+        let parent_based = |inner| Self::ParentBased(Box::new(inner));
+        match name.trim() {
+            "always_on" => Some(Self::AlwaysOn),
+            "always_off" => Some(Self::AlwaysOff),
+            "traceidratio" => Some(Self::TraceIdRatio(ratio())),
+            "parentbased_always_on" => Some(parent_based(Self::AlwaysOn)),
+            "parentbased_always_off" => Some(parent_based(Self::AlwaysOff)),
+            "parentbased_traceidratio" => Some(parent_based(Self::TraceIdRatio(ratio()))),
+            _ => None,
+        }
+    }
+
+    /// Code configuration first, then the environment, then the SDK default.
+    pub(crate) fn resolve(configured: Option<&Self>) -> Self {
+        configured
+            .cloned()
+            .or_else(Self::from_env)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn build(&self) -> Box<dyn ShouldSample> {
+        match self {
+            Self::AlwaysOn => Box::new(Sampler::AlwaysOn),
+            Self::AlwaysOff => Box::new(Sampler::AlwaysOff),
+            Self::TraceIdRatio(ratio) => Box::new(Sampler::TraceIdRatioBased(*ratio)),
+            Self::ParentBased(inner) => Box::new(Sampler::ParentBased(inner.build())),
+            Self::Rules { rules, fallback } => Box::new(RuleBasedSampler {
+                rules: rules
+                    .iter()
+                    .map(|rule| (rule.clone(), rule.sampler.build()))
+                    .collect(),
+                fallback: fallback.build(),
+            }),
+        }
+    }
+}
+
+impl Display for SamplerConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlwaysOn => write!(f, "always_on"),
+            Self::AlwaysOff => write!(f, "always_off"),
+            Self::TraceIdRatio(ratio) => write!(f, "traceidratio({ratio})"),
+            Self::ParentBased(inner) => write!(f, "parentbased({inner})"),
+            Self::Rules { rules, fallback } => {
+                write!(f, "rules(count={}, fallback={fallback})", rules.len())
+            }
+        }
+    }
+}
+
+/// Lets a boxed sampler tree be installed with `with_sampler`.
+#[derive(Debug, Clone)]
+pub(crate) struct BoxedSampler(pub Box<dyn ShouldSample>);
+
+impl ShouldSample for BoxedSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.0
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RuleBasedSampler {
+    rules: Vec<(SamplingRule, Box<dyn ShouldSample>)>,
+    fallback: Box<dyn ShouldSample>,
+}
+
+impl ShouldSample for RuleBasedSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let sampler = self
+            .rules
+            .iter()
+            .find(|(rule, _)| rule.matches(name, attributes))
+            .map_or(&self.fallback, |(_, sampler)| sampler);
+        sampler.should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SamplerConfig, SamplingRule};
+    use opentelemetry::KeyValue;
+    use opentelemetry::trace::SamplingDecision;
+    use opentelemetry::trace::{SpanKind, TraceId};
+
+    fn decide(config: &SamplerConfig, name: &str, attributes: &[KeyValue]) -> SamplingDecision {
+        config
+            .build()
+            .should_sample(
+                None,
+                TraceId::from(42u128),
+                name,
+                &SpanKind::Internal,
+                attributes,
+                &[],
+            )
+            .decision
+    }
+
+    #[test]
+    pub fn rules_pick_first_match() {
+        let config = SamplerConfig::Rules {
+            rules: vec![
+                SamplingRule::new(SamplerConfig::AlwaysOff).span_name("health"),
+                SamplingRule::new(SamplerConfig::AlwaysOff).target("noisy::db"),
+                SamplingRule::new(SamplerConfig::AlwaysOn)
+                    .attribute("tenant", "vip")
+                    .target("svc"),
+            ],
+            fallback: Box::new(SamplerConfig::TraceIdRatio(0.0)),
+        };
+
+        let vip = [
+            KeyValue::new("target", "svc::api"),
+            KeyValue::new("tenant", "vip"),
+        ];
+        assert_eq!(decide(&config, "health", &vip), SamplingDecision::Drop);
+        assert_eq!(
+            decide(&config, "get_user", &vip),
+            SamplingDecision::RecordAndSample
+        );
+
+        let db = [KeyValue::new("target", "noisy::db::pool")];
+        assert_eq!(decide(&config, "query", &db), SamplingDecision::Drop);
+
+        // `svc_other` is not nested under `svc`
+        let other = [
+            KeyValue::new("target", "svc_other"),
+            KeyValue::new("tenant", "vip"),
+        ];
+        assert_eq!(decide(&config, "get_user", &other), SamplingDecision::Drop);
+    }
+
+    #[test]
+    pub fn env_values_are_parsed() {
+        assert_eq!(
+            SamplerConfig::from_spec("parentbased_traceidratio", Some("0.25")),
+            Some(SamplerConfig::ParentBased(Box::new(
+                SamplerConfig::TraceIdRatio(0.25)
+            )))
+        );
+        assert_eq!(
+            SamplerConfig::from_spec("traceidratio", Some("7")),
+            Some(SamplerConfig::TraceIdRatio(1.0))
+        );
+        assert_eq!(SamplerConfig::from_spec("xray", None), None);
+        assert_eq!(
+            SamplerConfig::default().to_string(),
+            "parentbased(always_on)"
+        );
+    }
+}
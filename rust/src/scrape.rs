@@ -0,0 +1,86 @@
+use std::error::Error;
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use prometheus::{Registry, TEXT_FORMAT, TextEncoder};
+
+/// Reader collecting the meter provider into `registry` on every scrape.
+pub(crate) fn reader(
+    registry: &Registry,
+) -> Result<opentelemetry_prometheus::PrometheusExporter, Box<dyn Error + Send + Sync>> {
+    Ok(opentelemetry_prometheus::exporter()
+        .with_registry(registry.clone())
+        .build()?)
+}
+
+/// Axum handler rendering `registry` in the Prometheus text exposition format.
+pub async fn metrics_handler(State(registry): State<Registry>) -> Response {
+    match TextEncoder::new().encode_to_string(&registry.gather()) {
+        Ok(body) => ([(CONTENT_TYPE, TEXT_FORMAT)], body).into_response(),
+        Err(error) => {
+            tracing::error!(error = %error, "Failed to encode Prometheus metrics");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Router serving `GET /metrics`, merge it into the application router or
+/// serve it on a separate port.
+pub fn metrics_router<S>(registry: Registry) -> Router<S> {
+    Router::new()
+        .route("/metrics", axum::routing::get(metrics_handler))
+        .with_state(registry)
+}
+
+#[cfg(all(test, feature = "otel-in-memory"))]
+mod test {
+    use super::metrics_router;
+    use crate::OtelParams;
+    use axum::http::{Request, StatusCode};
+    use opentelemetry::KeyValue;
+    use opentelemetry::metrics::MeterProvider;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    pub async fn metrics_are_served_next_to_periodic_export() {
+        let params = OtelParams {
+            prometheus: true,
+            exporter_metrics: crate::SignalExporter::InMemory,
+            ..Default::default()
+        };
+        let resource = opentelemetry_sdk::Resource::builder()
+            .with_service_name("svc")
+            .build();
+        let mut guards = crate::OtelGuards::default();
+        let provider = crate::build_meter_provider(&params, resource, &mut guards)
+            .unwrap()
+            .unwrap();
+        let counter = provider.meter("test").u64_counter("orders").build();
+        counter.add(2, &[KeyValue::new("status", "ok")]);
+
+        let app: axum::Router = metrics_router(guards.prometheus.clone().unwrap());
+        let response = app
+            .oneshot(
+                Request::get("/metrics")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"orders_total{status="ok",otel_scope_name="test"} 2"#));
+        assert!(body.contains(r#"service_name="svc""#));
+
+        // The periodic exporter still receives the same instruments.
+        provider.force_flush().unwrap();
+        let exporter = guards.in_memory.metrics.as_ref().unwrap();
+        assert_eq!(crate::test::metric_sum(exporter, "orders"), 2);
+    }
+}
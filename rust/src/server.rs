@@ -0,0 +1,280 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::extract::MatchedPath;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use tracing::Instrument;
+use tracing::field::Empty;
+
+use crate::{HttpRequestMetrics, IntoOtelAttributes, MetricsHelper};
+
+/// Tower layer tracing and measuring every request of an axum router.
+///
+/// Each request gets a server span named after the matched route template,
+/// parented to the remote context in its headers, and is recorded in
+/// `MetricsHelper::http_request_counter` / `http_request_duration`. Add it
+/// with `Router::layer` so the matched route is known.
+#[derive(Clone)]
+pub struct HttpServerLayer {
+    counter: Counter<u64>,
+    duration: Histogram<u64>,
+}
+
+impl HttpServerLayer {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            counter: MetricsHelper::http_request_counter(meter),
+            duration: MetricsHelper::http_request_duration(meter),
+        }
+    }
+}
+
+impl<S> tower_layer::Layer<S> for HttpServerLayer {
+    type Service = HttpServerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpServerService {
+            inner,
+            counter: self.counter.clone(),
+            duration: self.duration.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpServerService<S> {
+    inner: S,
+    counter: Counter<u64>,
+    duration: Histogram<u64>,
+}
+
+impl<S, B, R> tower_service::Service<http::Request<B>> for HttpServerService<S>
+where
+    S: tower_service::Service<http::Request<B>, Response = http::Response<R>>,
+    S::Error: std::fmt::Display,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let start = Instant::now();
+        let method = crate::method::normalize(request.method());
+        // Unmatched requests (fallback, 404) get no route, raw paths would
+        // blow up metric cardinality.
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_owned())
+            .unwrap_or_default();
+        let name = match crate::method::span_name(method) {
+            method if route.is_empty() => method.to_owned(),
+            method => format!("{method} {route}"),
+        };
+
+        let span = tracing::info_span!(
+            "http.request",
+            otel.name = %name,
+            otel.kind = "server",
+            otel.status_code = Empty,
+            http.request.method = method,
+            http.request.method_original = Empty,
+            http.route = %route,
+            url.path = %request.uri().path(),
+            http.response.status_code = Empty,
+            error.type = Empty,
+        );
+        if method == crate::method::OTHER {
+            span.record("http.request.method_original", request.method().as_str());
+        }
+        _ = crate::set_parent_from_headers(&span, request.headers());
+
+        let future = self.inner.call(request);
+        let counter = self.counter.clone();
+        let duration = self.duration.clone();
+        Box::pin(
+            async move {
+                let result = future.await;
+                let span = tracing::Span::current();
+                let mut metrics = HttpRequestMetrics::new().route(route);
+                metrics.method = method.to_owned();
+
+                match &result {
+                    Ok(response) => {
+                        let status = response.status();
+                        span.record("http.response.status_code", status.as_u16());
+                        metrics = metrics.status_code(status.as_u16());
+                        // Only 5xx marks a server span as failed, 4xx are the
+                        // client's fault.
+                        if status.is_server_error() {
+                            span.record("otel.status_code", "ERROR");
+                            span.record("error.type", status.as_str());
+                            metrics = metrics.error(status.as_str());
+                        }
+                    }
+                    Err(error) => {
+                        span.record("otel.status_code", "ERROR");
+                        span.record("error.type", "_OTHER");
+                        tracing::error!(error = %error, "Request failed");
+                        metrics = metrics.internal_server_error().error("_OTHER");
+                    }
+                }
+
+                let metrics = metrics.duration(start.elapsed().as_millis() as u64);
+                let attributes = metrics.into_attributes();
+                counter.add(1, &attributes);
+                if let Some(ms) = metrics.duration_ms {
+                    duration.record(ms, &attributes);
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HttpServerLayer;
+    use axum::Router;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use opentelemetry::trace::{SpanId, Status, TracerProvider};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use tower::ServiceExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    pub async fn requests_are_traced_and_measured() {
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let spans = InMemorySpanExporter::default();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spans.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let metrics = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let layer = HttpServerLayer::new(&opentelemetry::metrics::MeterProvider::meter(
+            &meter_provider,
+            "test",
+        ));
+
+        let app = Router::new()
+            .route("/orders/{id}", get(|| async { "ok" }))
+            .route("/fail", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+            .layer(layer);
+
+        let request = Request::get("/orders/42")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = Request::get("/fail")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let finished = spans.get_finished_spans().unwrap();
+        let order = finished
+            .iter()
+            .find(|s| s.name == "GET /orders/{id}")
+            .unwrap();
+        assert_eq!(order.parent_span_id, SpanId::from(0x00f067aa0ba902b7));
+        assert_eq!(order.span_kind, opentelemetry::trace::SpanKind::Server);
+        let failed = finished.iter().find(|s| s.name == "GET /fail").unwrap();
+        assert!(matches!(failed.status, Status::Error { .. }));
+
+        meter_provider.force_flush().unwrap();
+        assert_eq!(
+            crate::test::metric_sum(&metrics, "http.server.request.total"),
+            2
+        );
+    }
+
+    #[tokio::test]
+    pub async fn unknown_methods_are_reported_as_other() {
+        use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+
+        let spans = InMemorySpanExporter::default();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(spans.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let metrics = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build())
+            .build();
+        let layer = HttpServerLayer::new(&opentelemetry::metrics::MeterProvider::meter(
+            &meter_provider,
+            "test",
+        ));
+        let app = Router::new()
+            .route("/orders", axum::routing::any(|| async { "ok" }))
+            .layer(layer);
+
+        for method in ["FOO1", "FOO2"] {
+            let request = Request::builder()
+                .method(method)
+                .uri("/orders")
+                .body(axum::body::Body::empty())
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let finished = spans.get_finished_spans().unwrap();
+        assert!(finished.iter().all(|span| span.name == "HTTP /orders"));
+        let attribute = |key: &str| {
+            finished[0]
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.to_string())
+        };
+        assert_eq!(attribute("http.request.method").as_deref(), Some("_OTHER"));
+        assert_eq!(
+            attribute("http.request.method_original").as_deref(),
+            Some("FOO1")
+        );
+
+        meter_provider.force_flush().unwrap();
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let total = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == "http.server.request.total")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = total.data() else {
+            panic!("http.server.request.total is not a sum");
+        };
+        let points: Vec<_> = sum.data_points().collect();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].value(), 2);
+        assert!(points[0].attributes().any(|kv| {
+            kv.key.as_str() == "http.request.method" && kv.value.as_str() == "_OTHER"
+        }));
+    }
+}
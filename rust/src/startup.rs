@@ -0,0 +1,213 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::ConsoleWriter;
+
+pub(crate) const STARTUP_TARGET: &str = "internal_utils::startup";
+
+/// Snapshot of the telemetry configuration, emitted once by `try_init`.
+pub(crate) struct StartupSummary {
+    pub sinks: Vec<&'static str>,
+    pub json: bool,
+    pub console: Option<ConsoleWriter>,
+    pub file: Option<String>,
+    pub filter: String,
+    #[cfg(feature = "otel")]
+    pub otel: Option<OtelSummary>,
+}
+
+#[cfg(feature = "otel")]
+pub(crate) struct OtelSummary {
+    pub service_name: String,
+    pub service_version: String,
+    pub endpoint_traces: Option<String>,
+    pub endpoint_metrics: Option<String>,
+    pub endpoint_logs: Option<String>,
+    pub sampler: String,
+    pub propagators: String,
+    pub sdk_version: String,
+}
+
+#[cfg(feature = "otel")]
+impl OtelSummary {
+    pub fn new(params: &crate::OtelParams) -> Self {
+        use opentelemetry_semantic_conventions::resource::TELEMETRY_SDK_VERSION;
+
+        // Read from the SDK's own resource detector so the value matches the
+        // resource attached to exported telemetry.
+        let sdk_version = opentelemetry_sdk::Resource::builder()
+            .build()
+            .get(&opentelemetry::Key::from_static_str(TELEMETRY_SDK_VERSION))
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+
+        Self {
+            service_name: params.service_name.clone(),
+            service_version: params.service_version.clone(),
+            endpoint_traces: destination(
+                params.exporter_traces,
+                params.traces_enabled,
+                params.traces_endpoint(),
+            ),
+            endpoint_metrics: destination(
+                params.exporter_metrics,
+                params.metrics_enabled,
+                params.metrics_endpoint(),
+            ),
+            endpoint_logs: destination(
+                params.exporter_logs,
+                params.logs_enabled,
+                params.logs_endpoint(),
+            ),
+            sampler: crate::SamplerConfig::resolve(params.sampler.as_ref()).to_string(),
+            propagators: crate::PropagationFormat::resolve(params.propagators.as_deref())
+                .iter()
+                .map(|format| format.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            sdk_version,
+        }
+    }
+}
+
+/// Lets the summary through `filter` unless a directive already names its
+/// target, so `RUST_LOG=warn` does not hide it.
+pub(crate) fn enable(filter: EnvFilter) -> EnvFilter {
+    if filter.to_string().contains(STARTUP_TARGET) {
+        return filter;
+    }
+    match format!("{STARTUP_TARGET}=info").parse() {
+        Ok(directive) => filter.add_directive(directive),
+        Err(_) => filter,
+    }
+}
+
+impl StartupSummary {
+    pub fn emit(&self) {
+        let sinks = self.sinks.join(",");
+        let format = if self.json { "json" } else { "text" };
+        let console = self
+            .console
+            .map(|c| format!("{c:?}"))
+            .unwrap_or_else(|| "disabled".to_owned());
+        let file = self.file.as_deref().unwrap_or("disabled");
+
+        #[cfg(feature = "otel")]
+        if let Some(otel) = &self.otel {
+            let disabled = || "disabled".to_owned();
+            tracing::info!(
+                target: STARTUP_TARGET,
+                sinks,
+                format,
+                console,
+                file,
+                filter = %self.filter,
+                service.name = %otel.service_name,
+                service.version = %otel.service_version,
+                otel.endpoint.traces = otel.endpoint_traces.clone().unwrap_or_else(disabled),
+                otel.endpoint.metrics = otel.endpoint_metrics.clone().unwrap_or_else(disabled),
+                otel.endpoint.logs = otel.endpoint_logs.clone().unwrap_or_else(disabled),
+                otel.sampler = %otel.sampler,
+                otel.propagators = %otel.propagators,
+                telemetry.sdk.version = %otel.sdk_version,
+                internal_utils.version = env!("CARGO_PKG_VERSION"),
+                "Telemetry initialized"
+            );
+            return;
+        }
+
+        tracing::info!(
+            target: STARTUP_TARGET,
+            sinks,
+            format,
+            console,
+            file,
+            filter = %self.filter,
+            internal_utils.version = env!("CARGO_PKG_VERSION"),
+            "Telemetry initialized"
+        );
+    }
+}
+
+/// Stripped OTLP endpoint, or the exporter name for stdout and in-memory.
+#[cfg(feature = "otel")]
+fn destination(
+    exporter: crate::SignalExporter,
+    enabled: bool,
+    endpoint: Option<String>,
+) -> Option<String> {
+    if exporter == crate::SignalExporter::Otlp {
+        endpoint.as_deref().map(crate::url::strip_credentials)
+    } else {
+        enabled.then(|| exporter.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StartupSummary;
+    use crate::ConsoleWriter;
+    use crate::test::BufferWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    pub fn summary_is_emitted() {
+        let buffer = BufferWriter::default();
+        let writer = buffer.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(move || writer.clone());
+        let summary = StartupSummary {
+            sinks: vec!["stdout", "otlp"],
+            json: true,
+            console: Some(ConsoleWriter::SplitByLevel),
+            file: None,
+            filter: "info".to_owned(),
+            #[cfg(feature = "otel")]
+            otel: Some(super::OtelSummary::new(&crate::OtelParams::local(
+                "svc".into(),
+                "1.0.0".into(),
+            ))),
+        };
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || summary.emit());
+
+        let output = buffer.contents();
+        assert!(output.contains(r#""sinks":"stdout,otlp""#));
+        assert!(output.contains(r#""console":"SplitByLevel""#));
+        assert!(output.contains(r#""file":"disabled""#));
+        #[cfg(feature = "otel")]
+        {
+            assert!(output.contains(r#""otel.endpoint.traces":"http://localhost:4318/v1/traces""#));
+            assert!(output.contains(r#""telemetry.sdk.version":"0."#));
+        }
+    }
+
+    #[test]
+    pub fn summary_passes_a_stricter_filter() {
+        let buffer = BufferWriter::default();
+        let writer = buffer.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(move || writer.clone());
+        let summary = StartupSummary {
+            sinks: vec!["console"],
+            json: false,
+            console: Some(ConsoleWriter::Stdout),
+            file: None,
+            filter: "warn".to_owned(),
+            #[cfg(feature = "otel")]
+            otel: None,
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layer)
+            .with(super::enable(tracing_subscriber::EnvFilter::new("warn")));
+        tracing::subscriber::with_default(subscriber, || {
+            summary.emit();
+            tracing::info!(target: "app", "filtered out");
+        });
+
+        let output = buffer.contents();
+        assert!(output.contains("Telemetry initialized"));
+        assert!(!output.contains("filtered out"));
+    }
+}
@@ -0,0 +1,483 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+/// Example private enterprise number from RFC 5612, used for SD-IDs.
+const DEFAULT_SD_ID: &str = "fields@32473";
+
+/// Messages waiting for the writer thread, newer ones are dropped when full.
+const QUEUE_CAPACITY: usize = 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub enum SyslogTransport {
+    /// `host:port`, one datagram per message
+    Udp(String),
+    /// `host:port`, octet-counting framing (RFC 6587)
+    Tcp(String),
+    /// Datagram Unix socket, e.g. `/dev/log`
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFacility {
+    Kern = 0,
+    #[default]
+    User = 1,
+    Daemon = 3,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyslogParams {
+    pub transport: SyslogTransport,
+    pub facility: SyslogFacility,
+    pub app_name: String,
+    /// Defaults to the kernel hostname when None
+    pub hostname: Option<String>,
+    /// SD-ID of the element carrying event fields
+    pub structured_data_id: String,
+}
+
+impl SyslogParams {
+    pub fn new(transport: SyslogTransport, app_name: impl Into<String>) -> Self {
+        Self {
+            transport,
+            facility: SyslogFacility::default(),
+            app_name: app_name.into(),
+            hostname: None,
+            structured_data_id: DEFAULT_SD_ID.to_owned(),
+        }
+    }
+
+    pub fn local_udp(app_name: impl Into<String>) -> Self {
+        Self::new(SyslogTransport::Udp("127.0.0.1:514".into()), app_name)
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpConnection),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Connection {
+    fn open(transport: &SyslogTransport) -> std::io::Result<Self> {
+        match transport {
+            SyslogTransport::Udp(addr) => {
+                let addr = resolve(addr)?;
+                let local: SocketAddr = if addr.is_ipv6() {
+                    "[::]:0".parse().expect("valid address")
+                } else {
+                    "0.0.0.0:0".parse().expect("valid address")
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Self::Udp(socket))
+            }
+            SyslogTransport::Tcp(addr) => Ok(Self::Tcp(TcpConnection {
+                stream: Some(connect(addr)?),
+                addr: addr.clone(),
+                backoff: MIN_BACKOFF,
+                retry_at: None,
+            })),
+            #[cfg(unix)]
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(Self::Unix(socket))
+            }
+        }
+    }
+
+    fn send(&mut self, message: &str) -> std::io::Result<()> {
+        match self {
+            Self::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Self::Tcp(connection) => connection.send(message),
+            #[cfg(unix)]
+            Self::Unix(socket) => socket.send(message.as_bytes()).map(|_| ()),
+        }
+    }
+}
+
+/// TCP stream reconnected on failure, with exponential backoff between
+/// attempts so an unreachable collector costs one timeout per backoff period.
+struct TcpConnection {
+    addr: String,
+    stream: Option<TcpStream>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl TcpConnection {
+    fn send(&mut self, message: &str) -> std::io::Result<()> {
+        let frame = format!("{} {}", message.len(), message);
+        if let Some(stream) = &mut self.stream {
+            if stream.write_all(frame.as_bytes()).is_ok() {
+                return Ok(());
+            }
+            self.stream = None;
+        }
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "syslog collector unreachable, waiting to reconnect",
+            ));
+        }
+        let result = connect(&self.addr).and_then(|mut stream| {
+            stream.write_all(frame.as_bytes())?;
+            Ok(stream)
+        });
+        match result {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.backoff = MIN_BACKOFF;
+                self.retry_at = None;
+                Ok(())
+            }
+            Err(error) => {
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Err(error)
+            }
+        }
+    }
+}
+
+fn resolve(addr: &str) -> std::io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("no address for {addr}"),
+        )
+    })
+}
+
+/// Tries every resolved address with `CONNECT_TIMEOUT`.
+fn connect(addr: &str) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("no address for {addr}"),
+        )
+    }))
+}
+
+/// Writes queued messages until every sender is gone.
+fn write_loop(mut connection: Connection, messages: Receiver<String>) {
+    for message in messages {
+        _ = connection.send(&message);
+    }
+}
+
+/// Layer emitting RFC 5424 syslog messages.
+///
+/// Event fields other than `message` are carried as structured data.
+/// Messages are sent from a background thread, so a slow or unreachable
+/// collector never blocks logging threads. Messages are dropped while its
+/// queue is full.
+pub struct SyslogLayer {
+    params: SyslogParams,
+    hostname: String,
+    sender: SyncSender<String>,
+}
+
+impl SyslogLayer {
+    pub fn new(params: SyslogParams) -> std::io::Result<Self> {
+        let connection = Connection::open(&params.transport)?;
+        let hostname = params.hostname.clone().unwrap_or_else(local_hostname);
+        let (sender, receiver) = sync_channel(QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("syslog-writer".into())
+            .spawn(move || write_loop(connection, receiver))?;
+        Ok(Self {
+            params,
+            hostname,
+            sender,
+        })
+    }
+
+    fn format(&self, event: &Event<'_>) -> String {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let pri = (self.params.facility as u8) * 8 + severity(metadata.level());
+        let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+
+        let mut sd = String::new();
+        _ = write!(
+            sd,
+            "[{} target=\"{}\"",
+            self.params.structured_data_id,
+            escape_sd_value(metadata.target())
+        );
+        for (name, value) in visitor.fields.iter() {
+            _ = write!(sd, " {}=\"{}\"", sd_name(name), escape_sd_value(value));
+        }
+        sd.push(']');
+
+        format!(
+            "<{}>1 {} {} {} {} - {} {}",
+            pri,
+            timestamp,
+            header_field(&self.hostname, 255),
+            header_field(&self.params.app_name, 48),
+            std::process::id(),
+            sd,
+            visitor.message
+        )
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        _ = self.sender.try_send(self.format(event));
+    }
+}
+
+/// Maps tracing levels to RFC 5424 severities.
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+fn local_hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_owned())
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// Header fields are printable US-ASCII without spaces, NILVALUE when empty.
+fn header_field(value: &str, max_len: usize) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if value.is_empty() {
+        "-".to_owned()
+    } else {
+        value
+    }
+}
+
+/// SD-NAMEs exclude `=`, space, `]` and `"`, at most 32 characters, `_`
+/// when nothing is left since they cannot be empty.
+fn sd_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect();
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name
+    }
+}
+
+fn escape_sd_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.fields.push((field.name(), value.to_owned()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields.push((field.name(), format!("{value:?}")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SyslogLayer, SyslogParams, SyslogTransport, sd_name};
+    use std::io::Read;
+    use std::net::{TcpListener, UdpSocket};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    pub fn udp_message_is_rfc5424() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut params = SyslogParams::new(SyslogTransport::Udp(addr), "test-app");
+        params.hostname = Some("test-host".into());
+        let layer = SyslogLayer::new(params).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(target: "svc", user_id = "u\"1", attempt = 3, "login failed");
+        });
+
+        let mut buf = [0u8; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+
+        // user (1) * 8 + warning (4)
+        assert!(message.starts_with("<12>1 "), "{message}");
+        assert!(message.contains(" test-host test-app "));
+        assert!(
+            message
+                .contains(r#"[fields@32473 target="svc" user_id="u\"1" attempt="3"] login failed"#)
+        );
+    }
+
+    #[test]
+    pub fn tcp_message_uses_octet_counting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let layer =
+            SyslogLayer::new(SyslogParams::new(SyslogTransport::Tcp(addr), "test-app")).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || tracing::error!("disk full"));
+
+        let mut buf = [0u8; 1024];
+        let len = stream.read(&mut buf).unwrap();
+        let frame = std::str::from_utf8(&buf[..len]).unwrap();
+        let (count, message) = frame.split_once(' ').unwrap();
+
+        assert_eq!(count.parse::<usize>().unwrap(), message.len());
+        // user (1) * 8 + error (3)
+        assert!(message.starts_with("<11>1 "));
+        assert!(message.ends_with("] disk full"));
+    }
+
+    #[test]
+    pub fn udp_reaches_ipv6_collectors() {
+        let Ok(listener) = UdpSocket::bind("[::1]:0") else {
+            // No IPv6 loopback in this environment.
+            return;
+        };
+        let addr = listener.local_addr().unwrap().to_string();
+        let layer =
+            SyslogLayer::new(SyslogParams::new(SyslogTransport::Udp(addr), "test-app")).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || tracing::info!("over v6"));
+
+        let mut buf = [0u8; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        assert!(
+            std::str::from_utf8(&buf[..len])
+                .unwrap()
+                .ends_with("] over v6")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    pub fn unix_socket_receives_datagrams() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!("syslog-test-{}.sock", std::process::id()));
+        _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let layer = SyslogLayer::new(SyslogParams::new(
+            SyslogTransport::Unix(path.clone()),
+            "test-app",
+        ))
+        .unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || tracing::info!(queue = "blocks", "ready"));
+
+        let mut buf = [0u8; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        _ = std::fs::remove_file(&path);
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        // user (1) * 8 + informational (6)
+        assert!(message.starts_with("<14>1 "), "{message}");
+        assert!(message.ends_with(r#" queue="blocks"] ready"#), "{message}");
+    }
+
+    #[test]
+    pub fn unreachable_tcp_collector_does_not_block() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let layer =
+            SyslogLayer::new(SyslogParams::new(SyslogTransport::Tcp(addr), "test-app")).unwrap();
+        drop(listener);
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let start = std::time::Instant::now();
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..super::QUEUE_CAPACITY * 2 {
+                tracing::error!("collector down");
+            }
+        });
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    pub fn sd_names_are_never_empty() {
+        assert_eq!(sd_name("user.id"), "user.id");
+        assert_eq!(sd_name("a b=\"c\""), "abc");
+        assert_eq!(sd_name("\"=]"), "_");
+    }
+}
@@ -0,0 +1,514 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use opentelemetry::trace::{SpanId, Status, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+
+/// Local tail sampling of whole traces.
+///
+/// Spans are buffered per trace until the local root ends or `decision_wait`
+/// elapses, checked by a background thread. Traces with an error status or a span slower than
+/// `latency_threshold` are always kept, the rest are kept at `success_ratio`.
+/// Pair it with an always-on head sampler, spans dropped at the head never
+/// reach this processor.
+#[derive(Debug, Clone)]
+pub struct TailSamplingConfig {
+    /// Longest time a trace is buffered before a decision is forced
+    pub decision_wait: Duration,
+    pub latency_threshold: Option<Duration>,
+    /// Ratio in `0.0..=1.0` for successful, fast traces
+    pub success_ratio: f64,
+    /// Buffered traces, the oldest is decided early when exceeded
+    pub max_traces: usize,
+    /// Spans kept per trace, later spans are dropped
+    pub max_spans_per_trace: usize,
+}
+
+impl Default for TailSamplingConfig {
+    fn default() -> Self {
+        Self {
+            decision_wait: Duration::from_secs(10),
+            latency_threshold: Some(Duration::from_secs(1)),
+            success_ratio: 0.1,
+            max_traces: 10_000,
+            max_spans_per_trace: 1_000,
+        }
+    }
+}
+
+struct PendingTrace {
+    first_seen: Instant,
+    spans: Vec<SpanData>,
+    keep: bool,
+}
+
+#[derive(Default)]
+struct State {
+    pending: HashMap<TraceId, PendingTrace>,
+    /// Insertion order of `pending`, may contain already decided ids
+    order: VecDeque<TraceId>,
+    /// Decisions kept around so late spans follow their trace
+    decided: HashMap<TraceId, (bool, Instant)>,
+    /// Decision order of `decided`, entries are stale when the id was
+    /// decided again later
+    decided_order: VecDeque<(TraceId, Instant)>,
+}
+
+impl State {
+    fn remember(&mut self, trace_id: TraceId, keep: bool, now: Instant, max: usize) {
+        while self.decided.len() >= max.max(1) {
+            if !self.forget_oldest() {
+                break;
+            }
+        }
+        self.decided.insert(trace_id, (keep, now));
+        self.decided_order.push_back((trace_id, now));
+    }
+
+    /// Drops decisions older than `wait`.
+    fn expire(&mut self, now: Instant, wait: Duration) {
+        while self
+            .decided_order
+            .front()
+            .is_some_and(|(_, at)| now.duration_since(*at) >= wait)
+        {
+            self.forget_oldest();
+        }
+    }
+
+    fn forget_oldest(&mut self) -> bool {
+        let Some((trace_id, at)) = self.decided_order.pop_front() else {
+            return false;
+        };
+        if self
+            .decided
+            .get(&trace_id)
+            .is_some_and(|(_, when)| *when == at)
+        {
+            self.decided.remove(&trace_id);
+        }
+        true
+    }
+}
+
+struct DropCounters {
+    spans: opentelemetry::metrics::Counter<u64>,
+    traces: opentelemetry::metrics::Counter<u64>,
+}
+
+/// Span processor applying [`TailSamplingConfig`] in front of `inner`,
+/// usually a batch processor.
+pub struct TailSamplingProcessor<P> {
+    shared: Arc<Shared<P>>,
+    sweeper: Mutex<Sweeper>,
+}
+
+/// Background thread deciding expired traces even when no other span ends,
+/// started by the first span so `set_resource` still has sole ownership.
+enum Sweeper {
+    Idle,
+    /// Dropping the sender stops the thread
+    Running(Sender<()>, JoinHandle<()>),
+    Stopped,
+}
+
+/// State used by both the processor and its sweeper thread.
+struct Shared<P> {
+    inner: P,
+    config: TailSamplingConfig,
+    state: Mutex<State>,
+    counters: OnceLock<DropCounters>,
+}
+
+impl<P> std::fmt::Debug for TailSamplingProcessor<P>
+where
+    P: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TailSamplingProcessor")
+            .field("inner", &self.shared.inner)
+            .field("config", &self.shared.config)
+            .finish()
+    }
+}
+
+impl<P: SpanProcessor + 'static> TailSamplingProcessor<P> {
+    pub fn new(inner: P, config: TailSamplingConfig) -> Self {
+        let shared = Arc::new(Shared {
+            inner,
+            config,
+            state: Mutex::new(State::default()),
+            counters: OnceLock::new(),
+        });
+        Self {
+            shared,
+            sweeper: Mutex::new(Sweeper::Idle),
+        }
+    }
+
+    fn start_sweeper(&self) {
+        let mut sweeper = self.sweeper.lock().unwrap_or_else(|e| e.into_inner());
+        if !matches!(*sweeper, Sweeper::Idle) {
+            return;
+        }
+        let interval = (self.shared.config.decision_wait / 4)
+            .clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let shared = self.shared.clone();
+        let handle = std::thread::Builder::new()
+            .name("tail-sampling-sweeper".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    shared.sweep_and_export(Instant::now());
+                }
+            });
+        // Without the thread, traces are still swept when spans end.
+        *sweeper = match handle {
+            Ok(handle) => Sweeper::Running(stop, handle),
+            Err(_) => Sweeper::Stopped,
+        };
+    }
+
+    /// Stops the thread, `next` is `Idle` to allow a restart.
+    fn stop_sweeper(&self, next: Sweeper) {
+        let previous = {
+            let mut sweeper = self.sweeper.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *sweeper, next)
+        };
+        if let Sweeper::Running(stop, handle) = previous {
+            drop(stop);
+            _ = handle.join();
+        }
+    }
+}
+
+impl<P: SpanProcessor> Shared<P> {
+    /// Counters are created on first use so they bind to the meter provider
+    /// installed after the tracer provider.
+    fn counters(&self) -> &DropCounters {
+        self.counters.get_or_init(|| {
+            let meter = opentelemetry::global::meter("internal-utils");
+            DropCounters {
+                spans: meter
+                    .u64_counter("otel.tail_sampling.spans.dropped")
+                    .with_description("Spans dropped by local tail sampling")
+                    .with_unit("1")
+                    .build(),
+                traces: meter
+                    .u64_counter("otel.tail_sampling.traces")
+                    .with_description("Traces decided by local tail sampling")
+                    .with_unit("1")
+                    .build(),
+            }
+        })
+    }
+
+    fn record_drop(&self, count: u64, reason: &'static str) {
+        if count > 0 {
+            self.counters()
+                .spans
+                .add(count, &[KeyValue::new("reason", reason)]);
+        }
+    }
+
+    fn keep_by_ratio(&self, trace_id: TraceId) -> bool {
+        // Same trace id arithmetic as the SDK ratio sampler.
+        let ratio = self.config.success_ratio.clamp(0.0, 1.0);
+        let bound = (ratio * (1u64 << 63) as f64) as u64;
+        let id = u128::from_be_bytes(trace_id.to_bytes()) as u64;
+        (id >> 1) < bound
+    }
+
+    fn is_interesting(&self, span: &SpanData) -> bool {
+        if matches!(span.status, Status::Error { .. }) {
+            return true;
+        }
+        self.config.latency_threshold.is_some_and(|threshold| {
+            span.end_time
+                .duration_since(span.start_time)
+                .is_ok_and(|d| d >= threshold)
+        })
+    }
+
+    /// Returns the spans to export, if the trace is kept.
+    fn decide(&self, state: &mut State, trace_id: TraceId) -> Vec<SpanData> {
+        let Some(trace) = state.pending.remove(&trace_id) else {
+            return Vec::new();
+        };
+        let keep = trace.keep || self.keep_by_ratio(trace_id);
+        let decision = if keep { "sampled" } else { "dropped" };
+        self.counters()
+            .traces
+            .add(1, &[KeyValue::new("decision", decision)]);
+        state.remember(trace_id, keep, Instant::now(), self.config.max_traces);
+
+        if keep {
+            trace.spans
+        } else {
+            self.record_drop(trace.spans.len() as u64, "sampled_out");
+            Vec::new()
+        }
+    }
+
+    /// Decides traces past `decision_wait` and those over `max_traces`.
+    fn sweep(&self, state: &mut State, now: Instant) -> Vec<SpanData> {
+        let mut ready = Vec::new();
+        while let Some(trace_id) = state.order.front().copied() {
+            let expired = match state.pending.get(&trace_id) {
+                None => true,
+                Some(trace) => {
+                    state.pending.len() > self.config.max_traces
+                        || now.duration_since(trace.first_seen) >= self.config.decision_wait
+                }
+            };
+            if !expired {
+                break;
+            }
+            state.order.pop_front();
+            ready.extend(self.decide(state, trace_id));
+        }
+        state.expire(now, self.config.decision_wait);
+        ready
+    }
+
+    fn sweep_and_export(&self, now: Instant) {
+        let ready = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            self.sweep(&mut state, now)
+        };
+        self.export(ready);
+    }
+
+    fn export(&self, spans: Vec<SpanData>) {
+        for span in spans {
+            self.inner.on_end(span);
+        }
+    }
+
+    fn decide_all(&self) {
+        let ready = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let ids: Vec<_> = state.order.drain(..).collect();
+            let mut ready = Vec::new();
+            for trace_id in ids {
+                ready.extend(self.decide(&mut state, trace_id));
+            }
+            ready
+        };
+        self.export(ready);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let now = Instant::now();
+        let trace_id = span.span_context.trace_id();
+        let is_local_root = span.parent_span_id == SpanId::INVALID || span.parent_span_is_remote;
+        let interesting = self.is_interesting(&span);
+
+        let ready = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let mut ready = Vec::new();
+
+            if let Some((keep, _)) = state.decided.get(&trace_id).copied() {
+                if keep {
+                    ready.push(span);
+                } else {
+                    self.record_drop(1, "sampled_out");
+                }
+            } else {
+                if !state.pending.contains_key(&trace_id) {
+                    state.order.push_back(trace_id);
+                }
+                let trace = state.pending.entry(trace_id).or_insert(PendingTrace {
+                    first_seen: now,
+                    spans: Vec::new(),
+                    keep: false,
+                });
+                trace.keep |= interesting;
+                if trace.spans.len() < self.config.max_spans_per_trace {
+                    trace.spans.push(span);
+                } else {
+                    self.record_drop(1, "span_limit");
+                }
+                if is_local_root {
+                    ready.extend(self.decide(&mut state, trace_id));
+                }
+            }
+
+            ready.extend(self.sweep(&mut state, now));
+            ready
+        };
+        self.export(ready);
+    }
+}
+
+impl<P: SpanProcessor + 'static> SpanProcessor for TailSamplingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.shared.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        self.start_sweeper();
+        self.shared.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.shared.decide_all();
+        self.shared.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.stop_sweeper(Sweeper::Stopped);
+        self.shared.decide_all();
+        self.shared.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        // A running sweeper holds the only other reference.
+        if Arc::get_mut(&mut self.shared).is_none() {
+            self.stop_sweeper(Sweeper::Idle);
+        }
+        if let Some(shared) = Arc::get_mut(&mut self.shared) {
+            shared.inner.set_resource(resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TailSamplingConfig, TailSamplingProcessor};
+    use opentelemetry::Context;
+    use opentelemetry::trace::{Status, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SimpleSpanProcessor};
+    use std::time::Duration;
+
+    fn provider(config: TailSamplingConfig) -> (SdkTracerProvider, InMemorySpanExporter) {
+        let exporter = InMemorySpanExporter::default();
+        let processor =
+            TailSamplingProcessor::new(SimpleSpanProcessor::new(exporter.clone()), config);
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(processor)
+            .build();
+        (provider, exporter)
+    }
+
+    fn exported_names(exporter: &InMemorySpanExporter) -> Vec<String> {
+        let mut names: Vec<_> = exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name.into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    pub fn errors_and_slow_traces_are_kept() {
+        let (provider, exporter) = provider(TailSamplingConfig {
+            latency_threshold: Some(Duration::from_millis(20)),
+            success_ratio: 0.0,
+            ..Default::default()
+        });
+        let tracer = provider.tracer("test");
+
+        tracer.in_span("fast_ok", |_| {
+            tracer.in_span("fast_ok_child", |_| {});
+        });
+        tracer.in_span("failed", |_| {
+            tracer.in_span("failed_child", |cx| {
+                cx.span().set_status(Status::error("boom"));
+            });
+        });
+        tracer.in_span("slow", |_| std::thread::sleep(Duration::from_millis(30)));
+
+        assert_eq!(
+            exported_names(&exporter),
+            vec!["failed", "failed_child", "slow"]
+        );
+    }
+
+    #[test]
+    pub fn pending_traces_are_bounded() {
+        let (provider, exporter) = provider(TailSamplingConfig {
+            success_ratio: 1.0,
+            max_traces: 1,
+            max_spans_per_trace: 1,
+            ..Default::default()
+        });
+        let tracer = provider.tracer("test");
+
+        // Root spans stay open, so only the limits force decisions.
+        let first = Context::current_with_span(tracer.start("first_root"));
+        {
+            let _guard = first.clone().attach();
+            tracer.in_span("first_a", |_| {});
+            tracer.in_span("first_b", |_| {});
+        }
+        assert!(exported_names(&exporter).is_empty());
+
+        let second = Context::current_with_span(tracer.start("second_root"));
+        {
+            let _guard = second.clone().attach();
+            tracer.in_span("second_a", |_| {});
+        }
+        // The first trace is evicted, its second span was over the limit.
+        assert_eq!(exported_names(&exporter), vec!["first_a"]);
+
+        provider.force_flush().unwrap();
+        assert_eq!(exported_names(&exporter), vec!["first_a", "second_a"]);
+    }
+
+    #[test]
+    pub fn expired_traces_are_decided_without_new_spans() {
+        let (provider, exporter) = provider(TailSamplingConfig {
+            decision_wait: Duration::from_millis(40),
+            success_ratio: 1.0,
+            ..Default::default()
+        });
+        let tracer = provider.tracer("test");
+
+        let root = Context::current_with_span(tracer.start("open_root"));
+        {
+            let _guard = root.clone().attach();
+            tracer.in_span("child", |_| {});
+        }
+        assert!(exported_names(&exporter).is_empty());
+
+        // No span ends from here on, only the sweeper can decide.
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        while exported_names(&exporter).is_empty() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(exported_names(&exporter), vec!["child"]);
+    }
+
+    #[test]
+    pub fn oldest_decisions_are_evicted_first() {
+        let (provider, exporter) = provider(TailSamplingConfig {
+            success_ratio: 1.0,
+            max_traces: 2,
+            ..Default::default()
+        });
+        let tracer = provider.tracer("test");
+
+        let mut late = None;
+        tracer.in_span("first", |_| {});
+        tracer.in_span("second", |cx| {
+            late = Some(tracer.start_with_context("second_late", &cx));
+        });
+        tracer.in_span("third", |_| {});
+        // The third decision evicts only the first, the second trace is
+        // still known as kept and its late span goes straight out.
+        drop(late);
+        assert_eq!(
+            exported_names(&exporter),
+            vec!["first", "second", "second_late", "third"]
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use opentelemetry::Key;
+use opentelemetry_sdk::metrics::{
+    Aggregation, Instrument, InstrumentKind, MeterProviderBuilder, Stream,
+};
+
+/// Changes how matching instruments are aggregated and exported, see
+/// `OtelParams::metric_views`. The first view matching an instrument wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricView {
+    /// Exact instrument name, or a prefix ending in `*`, e.g. `http.server.*`
+    pub instrument: String,
+    /// Exported name, only for an exact `instrument`
+    pub rename: Option<String>,
+    /// Explicit histogram bucket boundaries
+    pub boundaries: Option<Vec<f64>>,
+    /// Base-2 exponential histogram instead of explicit buckets
+    pub exponential: Option<ExponentialHistogram>,
+    /// Only these attribute keys are kept
+    pub allowed_attributes: Option<Vec<String>>,
+    /// These attribute keys are removed before aggregation. Applies to meters
+    /// of the global provider, e.g. `otel_meter`.
+    pub denied_attributes: Vec<String>,
+    /// Nothing is recorded or exported
+    pub drop: bool,
+    /// Distinct attribute sets before new ones are folded into the
+    /// `otel.metric.overflow` series, overrides `OtelParams::cardinality_limit`
+    pub cardinality_limit: Option<usize>,
+}
+
+impl MetricView {
+    pub fn new(instrument: impl Into<String>) -> Self {
+        Self {
+            instrument: instrument.into(),
+            rename: None,
+            boundaries: None,
+            exponential: None,
+            allowed_attributes: None,
+            denied_attributes: Vec::new(),
+            drop: false,
+            cardinality_limit: None,
+        }
+    }
+
+    pub fn rename(mut self, value: impl Into<String>) -> Self {
+        self.rename = Some(value.into());
+        self
+    }
+
+    pub fn boundaries(mut self, value: Vec<f64>) -> Self {
+        self.boundaries = Some(value);
+        self
+    }
+
+    pub fn exponential(mut self, value: ExponentialHistogram) -> Self {
+        self.exponential = Some(value);
+        self
+    }
+
+    pub fn allow_attributes<K: Into<String>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        self.allowed_attributes = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn deny_attributes<K: Into<String>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        self.denied_attributes = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn drop_instrument(mut self) -> Self {
+        self.drop = true;
+        self
+    }
+
+    pub fn cardinality_limit(mut self, value: usize) -> Self {
+        self.cardinality_limit = Some(value);
+        self
+    }
+
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        match self.instrument.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == self.instrument,
+        }
+    }
+}
+
+/// Base-2 exponential histogram aggregation, see `MetricView::exponential`
+/// and `OtelParams::exponential_histograms`.
+///
+/// Buckets adapt to the recorded range, so one instrument covers both
+/// sub-millisecond and minute-long durations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialHistogram {
+    /// Buckets per positive and negative range, SDK default 160
+    pub max_size: u32,
+    /// Highest resolution, from -10 to 20, SDK default 20
+    pub max_scale: i8,
+    pub record_min_max: bool,
+}
+
+impl Default for ExponentialHistogram {
+    fn default() -> Self {
+        Self {
+            max_size: 160,
+            max_scale: 20,
+            record_min_max: true,
+        }
+    }
+}
+
+impl ExponentialHistogram {
+    fn aggregation(&self) -> Result<Aggregation, String> {
+        if !(-10..=20).contains(&self.max_scale) {
+            return Err(format!(
+                "exponential histogram: max_scale {} is outside -10..=20",
+                self.max_scale
+            ));
+        }
+        if self.max_size == 0 {
+            return Err("exponential histogram: max_size must be above 0".into());
+        }
+        Ok(Aggregation::Base2ExponentialHistogram {
+            max_size: self.max_size,
+            max_scale: self.max_scale,
+            record_min_max: self.record_min_max,
+        })
+    }
+}
+
+/// SDK stream for an instrument, None when the default stream fits.
+///
+/// `histograms` is the global exponential setting, used for histograms
+/// whose view picks no aggregation. `cardinality_limit` is the effective
+/// limit enforced by `AttributePolicy`.
+fn stream(
+    view: Option<&MetricView>,
+    histogram: bool,
+    histograms: Option<ExponentialHistogram>,
+    cardinality_limit: Option<usize>,
+) -> Result<Option<Stream>, String> {
+    let context = |e: String| match view {
+        Some(view) => format!("metric view `{}`: {e}", view.instrument),
+        None => e,
+    };
+
+    let aggregation = match view {
+        Some(view) if view.drop => Some(Aggregation::Drop),
+        Some(MetricView {
+            boundaries: Some(_),
+            exponential: Some(_),
+            ..
+        }) => {
+            return Err(context(
+                "boundaries and exponential exclude each other".into(),
+            ));
+        }
+        Some(MetricView {
+            boundaries: Some(boundaries),
+            ..
+        }) => Some(Aggregation::ExplicitBucketHistogram {
+            boundaries: boundaries.clone(),
+            record_min_max: true,
+        }),
+        Some(MetricView {
+            exponential: Some(exponential),
+            ..
+        }) => Some(exponential.aggregation().map_err(context)?),
+        _ if histogram => histograms
+            .map(|exponential| exponential.aggregation())
+            .transpose()?,
+        _ => None,
+    };
+    let rename = view.and_then(|view| view.rename.clone());
+    let allowed = view.and_then(|view| view.allowed_attributes.clone());
+    if aggregation.is_none() && rename.is_none() && allowed.is_none() && cardinality_limit.is_none()
+    {
+        return Ok(None);
+    }
+    if cardinality_limit == Some(0) {
+        return Err(context("cardinality limit must be above 0".into()));
+    }
+    if let Some(view) = view
+        && rename.is_some()
+        && view.instrument.ends_with('*')
+    {
+        return Err(context("rename needs an exact instrument name".into()));
+    }
+
+    let mut stream = Stream::builder();
+    if let Some(name) = rename {
+        stream = stream.with_name(name);
+    }
+    if let Some(aggregation) = aggregation {
+        stream = stream.with_aggregation(aggregation);
+    }
+    if let Some(mut keys) = allowed {
+        if cardinality_limit.is_some() {
+            keys.push(crate::policy::OVERFLOW.to_owned());
+        }
+        stream = stream.with_allowed_attribute_keys(keys.into_iter().map(Key::new));
+    }
+    if let Some(limit) = cardinality_limit {
+        // Room for the overflow series, so the SDK never folds on its own.
+        stream = stream.with_cardinality_limit(limit + 1);
+    }
+    stream.build().map(Some).map_err(|e| context(e.to_string()))
+}
+
+/// Registers `views` and the global histogram and cardinality settings as a
+/// single SDK view so an instrument never gets more than one stream.
+pub(crate) fn register(
+    builder: MeterProviderBuilder,
+    views: &[MetricView],
+    histograms: Option<ExponentialHistogram>,
+    cardinality_limit: Option<usize>,
+) -> Result<MeterProviderBuilder, String> {
+    if views.is_empty() && histograms.is_none() && cardinality_limit.is_none() {
+        return Ok(builder);
+    }
+    for view in views {
+        let limit = view.cardinality_limit.or(cardinality_limit);
+        stream(Some(view), true, histograms, limit)?;
+    }
+    stream(None, true, histograms, cardinality_limit)?;
+    let views = views.to_vec();
+    Ok(builder.with_view(move |instrument: &Instrument| {
+        let view = views.iter().find(|view| view.matches(instrument.name()));
+        let histogram = instrument.kind() == InstrumentKind::Histogram;
+        let limit = view
+            .and_then(|view| view.cardinality_limit)
+            .or(cardinality_limit);
+        stream(view, histogram, histograms, limit).ok().flatten()
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExponentialHistogram, MetricView};
+    use crate::policy::AttributePolicy;
+    use opentelemetry::KeyValue;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    #[test]
+    pub fn views_reshape_instruments() {
+        let metrics = InMemoryMetricExporter::default();
+        let views = vec![
+            MetricView::new("rpc.duration").boundaries(vec![1.0, 10.0, 100.0]),
+            MetricView::new("orders")
+                .rename("orders.placed")
+                .deny_attributes(["user.id"]),
+            MetricView::new("requests").allow_attributes(["status"]),
+            MetricView::new("debug.*").drop_instrument(),
+        ];
+        let builder = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build());
+        let provider = super::register(builder, &views, None, None)
+            .unwrap()
+            .build();
+        let meter = AttributePolicy::new(&views, None)
+            .unwrap()
+            .wrap(provider.clone())
+            .meter("test");
+
+        meter
+            .u64_histogram("rpc.duration")
+            .with_boundaries(vec![5.0, 50.0])
+            .build()
+            .record(7, &[]);
+        meter.u64_counter("orders").build().add(
+            1,
+            &[
+                KeyValue::new("user.id", "42"),
+                KeyValue::new("status", "ok"),
+            ],
+        );
+        meter.u64_counter("requests").build().add(
+            1,
+            &[KeyValue::new("status", "ok"), KeyValue::new("path", "/a")],
+        );
+        meter.u64_counter("debug.cache").build().add(1, &[]);
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let exported: Vec<_> = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .collect();
+        let metric = |name: &str| exported.iter().find(|m| m.name() == name);
+        let keys = |name: &str| -> Vec<String> {
+            match metric(name).unwrap().data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => sum
+                    .data_points()
+                    .flat_map(|point| point.attributes())
+                    .map(|kv| kv.key.to_string())
+                    .collect(),
+                _ => unreachable!(),
+            }
+        };
+
+        let AggregatedMetrics::U64(MetricData::Histogram(histogram)) =
+            metric("rpc.duration").unwrap().data()
+        else {
+            panic!("rpc.duration is not a histogram");
+        };
+        let bounds: Vec<f64> = histogram.data_points().next().unwrap().bounds().collect();
+        assert_eq!(bounds, vec![1.0, 10.0, 100.0]);
+        assert!(metric("orders").is_none());
+        assert_eq!(keys("orders.placed"), vec!["status"]);
+        assert_eq!(keys("requests"), vec!["status"]);
+        assert!(metric("debug.cache").is_none());
+    }
+
+    #[test]
+    pub fn histograms_can_be_exponential() {
+        let metrics = InMemoryMetricExporter::default();
+        let views = [MetricView::new("rpc.duration").boundaries(vec![1.0, 10.0])];
+        let builder = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.clone()).build());
+        let provider =
+            super::register(builder, &views, Some(ExponentialHistogram::default()), None)
+                .unwrap()
+                .build();
+        let meter = provider.meter("test");
+
+        // The helper's explicit boundaries are replaced, its API is unchanged.
+        let duration = crate::MetricsHelper::http_request_duration(&meter);
+        for ms in [0, 1, 3, 45_000] {
+            duration.record(ms, &[]);
+        }
+        meter.u64_histogram("rpc.duration").build().record(4, &[]);
+        provider.force_flush().unwrap();
+
+        let snapshot = metrics.get_finished_metrics().unwrap();
+        let exported: Vec<_> = snapshot
+            .last()
+            .unwrap()
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .collect();
+        let data = |name: &str| exported.iter().find(|m| m.name() == name).unwrap().data();
+
+        let AggregatedMetrics::U64(MetricData::ExponentialHistogram(histogram)) =
+            data("http.server.request.duration")
+        else {
+            panic!("http.server.request.duration is not exponential");
+        };
+        let point = histogram.data_points().next().unwrap();
+        assert_eq!(point.count(), 4);
+        assert_eq!(point.max(), Some(45_000));
+        assert!(matches!(
+            data("rpc.duration"),
+            AggregatedMetrics::U64(MetricData::Histogram(_))
+        ));
+    }
+
+    #[test]
+    pub fn invalid_views_are_rejected() {
+        let builder = opentelemetry_sdk::metrics::SdkMeterProvider::builder();
+        assert!(
+            super::register(
+                builder,
+                &[MetricView::new("http.*").rename("http")],
+                None,
+                None
+            )
+            .is_err()
+        );
+        let builder = opentelemetry_sdk::metrics::SdkMeterProvider::builder();
+        assert!(
+            super::register(
+                builder,
+                &[MetricView::new("latency").boundaries(vec![10.0, 1.0])],
+                None,
+                None
+            )
+            .is_err()
+        );
+        let builder = opentelemetry_sdk::metrics::SdkMeterProvider::builder();
+        let exponential = ExponentialHistogram {
+            max_scale: 30,
+            ..Default::default()
+        };
+        assert!(super::register(builder, &[], Some(exponential), None).is_err());
+        let builder = opentelemetry_sdk::metrics::SdkMeterProvider::builder();
+        assert!(super::register(builder, &[], None, Some(0)).is_err());
+    }
+}